@@ -41,7 +41,7 @@ const PresentMonitorModal = () => {
                 await onPresentClick(
                   orgSlug,
                   projectSlug,
-                  i,
+                  monitor.id,
                   search,
                   selectedRendererId,
                 );
@@ -52,7 +52,8 @@ const PresentMonitorModal = () => {
               }}
               className="cursor-pointer hover:bg-surface-primary-hover"
             >
-              {monitor.name} | {monitor.size.width}x{monitor.size.height}
+              {monitor.name} | {monitor.width}x{monitor.height}
+              {monitor.is_primary && " (primary)"}
             </div>
           ))}
         </DialogBody>
@@ -75,7 +76,7 @@ const PresentMonitorModalWrapper = ({
   const { data: monitors } = useAvailableMonitors();
   const { data: allWindows, refetch: refetchWindow } = useAllWindows();
   const rendererWindow = useMemo(
-    () => allWindows?.find((x) => x.label === "renderer"),
+    () => allWindows?.find((x) => x.label === "renderer-main"),
     [allWindows],
   );
 
@@ -93,7 +94,7 @@ const PresentMonitorModalWrapper = ({
                 onPresentClick(
                   orgSlug,
                   projectSlug,
-                  0,
+                  monitors[0].id,
                   search,
                   selectedRendererId,
                 );
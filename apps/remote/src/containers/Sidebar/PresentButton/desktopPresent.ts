@@ -1,11 +1,14 @@
 import { core } from "@tauri-apps/api";
 
+import type { MonitorKey } from "./useAvailableMonitors";
+
 export const onPresentClick = async (
   orgSlug: string,
   projectSlug: string,
-  monitorIndex: number = 0,
+  monitor?: MonitorKey,
   search?: string,
   rendererId: string = "1",
+  outputId: string = "main",
 ) => {
   const basePath = `/render/${orgSlug}/${projectSlug}`;
   const rendererParam = `renderer=${rendererId}`;
@@ -15,6 +18,7 @@ export const onPresentClick = async (
 
   await core.invoke("open_renderer", {
     url: window.location.origin + fullPath,
-    mindex: monitorIndex,
+    monitor,
+    outputId,
   });
 };
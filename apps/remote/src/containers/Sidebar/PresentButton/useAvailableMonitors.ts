@@ -1,11 +1,32 @@
 import { useQuery } from "@tanstack/react-query";
-import { availableMonitors } from "@tauri-apps/api/window";
+import { core } from "@tauri-apps/api";
+
+export type MonitorKey = {
+  name: string | null;
+  width: number;
+  height: number;
+  x: number;
+  y: number;
+};
+
+export type OutputInfo = {
+  id: MonitorKey;
+  name: string | null;
+  width: number;
+  height: number;
+  x: number;
+  y: number;
+  scale_factor: number;
+  work_area: { x: number; y: number; width: number; height: number };
+  is_primary: boolean;
+  renderers: string[];
+};
 
 export const useAvailableMonitors = () => {
   return useQuery({
     queryKey: ["availableMonitors"],
     queryFn: () => {
-      return availableMonitors();
+      return core.invoke<OutputInfo[]>("list_outputs");
     },
   });
 };
@@ -0,0 +1,62 @@
+import { useKeyPressMutation } from "@repo/graphql";
+import { usePluginData, usePluginMetaData } from "@repo/shared";
+import { event } from "@tauri-apps/api";
+import { useEffect, useRef } from "react";
+
+// Mirrors `ShortcutAction` in tauri/src/shortcuts.rs
+type ShortcutAction =
+  | "next-slide"
+  | "previous-slide"
+  | "toggle-blackout"
+  | "toggle-logo"
+  | "toggle-freeze"
+  | "toggle-renderers"
+  | "clear";
+
+/**
+ * Carry out the Studio's global shortcuts for the slides. The Studio covers
+ * and hides renderers itself and only emits the rest as `global-shortcut`.
+ */
+export const useGlobalShortcuts = () => {
+  const [, keyPressMutate] = useKeyPressMutation();
+  const projectId = usePluginMetaData().projectId;
+  const { rendererId, mainState } = usePluginData();
+
+  // Keep one listener for the lifetime of the page, reading the latest values
+  const latest = useRef({ keyPressMutate, projectId, rendererId, mainState });
+  latest.current = { keyPressMutate, projectId, rendererId, mainState };
+
+  useEffect(() => {
+    if (!window.__TAURI_INTERNALS__) {
+      return;
+    }
+
+    const unlisten = event.listen<ShortcutAction>(
+      "global-shortcut",
+      ({ payload }) => {
+        const { keyPressMutate, projectId, rendererId, mainState } =
+          latest.current;
+
+        if (payload === "next-slide" || payload === "previous-slide") {
+          keyPressMutate({
+            keyType: payload === "next-slide" ? "NEXT" : "PREV",
+            projectId,
+            rendererId,
+          }).catch((e) => {
+            console.warn("Error triggering keyPress", e);
+          });
+        } else if (payload === "clear") {
+          const renderer = mainState?.renderer[rendererId];
+          if (renderer) {
+            renderer.overlay =
+              renderer.overlay?.type === "clear" ? null : { type: "clear" };
+          }
+        }
+      },
+    );
+
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+};
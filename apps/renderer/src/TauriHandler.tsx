@@ -1,6 +1,10 @@
+import { core } from "@tauri-apps/api";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 import { useEffect } from "react";
 
+// Lets the Studio notice when this page crashed or hung and reload it
+const HEARTBEAT_INTERVAL_MS = 2000;
+
 export const TauriHandler = ({ children }: { children: React.ReactNode }) => {
   useEffect(() => {
     const onKeyDown = (e: KeyboardEvent) => {
@@ -15,6 +19,13 @@ export const TauriHandler = ({ children }: { children: React.ReactNode }) => {
     };
   }, []);
 
+  useEffect(() => {
+    const interval = setInterval(() => {
+      core.invoke("renderer_heartbeat").catch(() => {});
+    }, HEARTBEAT_INTERVAL_MS);
+    return () => clearInterval(interval);
+  }, []);
+
   return children;
 };
 
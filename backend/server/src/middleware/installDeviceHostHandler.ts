@@ -1,6 +1,7 @@
 import { logger } from "@repo/observability";
 import axios from "axios";
 import { json } from "body-parser";
+import crypto from "crypto";
 import { Express } from "express";
 import { PoolClient } from "pg";
 
@@ -12,6 +13,10 @@ import { disposableDocumentManager } from "./installHocuspocus";
 const PING_INTERVAL_MS = 30000; // 30 seconds
 const JITTER_MAX_MS = 5000; // Maximum jitter of 5 seconds
 
+// Changes every time this server starts. The Tauri app compares it to tell
+// that a restarted server has forgotten its iroh connection info.
+const BOOT_ID = crypto.randomUUID();
+
 // Returns a random jitter value between 0 and JITTER_MAX_MS
 const getJitter = () => Math.random() * JITTER_MAX_MS;
 
@@ -98,7 +103,8 @@ export default (app: Express) => {
   };
 
   // This endpoint initializes the device host handler with iroh connection info.
-  // The rust code (Tauri) calls this once on startup.
+  // The rust code (Tauri) calls this on startup, and again whenever the ticket
+  // changes or this server restarts.
   // E2E tests can call it again to trigger an immediate sync of active projects.
   app.post("/device/host/init", json(), async (req, res) => {
     try {
@@ -144,13 +150,18 @@ export default (app: Express) => {
         console.log("Device re-synced");
       }
 
-      res.json({ success: true });
+      res.json({ success: true, bootId: BOOT_ID });
     } catch (err) {
       logger.error({ err }, "Error handling /device/host/init request");
       res.status(500).json({ error: "Internal server error" });
     }
   });
 
+  // Lets the Tauri app notice a server restart, however short
+  app.get("/device/host/status", (_req, res) => {
+    res.json({ bootId: BOOT_ID, initialized: isInitialized });
+  });
+
   // Stop the device host handler - stops all polling and resets state
   app.post("/device/host/stop", json(), async (req, res) => {
     try {
@@ -0,0 +1,126 @@
+//! Command line companion to the Studio's iroh bridge.
+//!
+//! Reads the same data dir as the app, so it can hand out the Studio's ticket,
+//! dial a Studio like `dumbpipe connect-tcp` would, or run the bridge headless.
+
+use std::{
+    net::{SocketAddr, SocketAddrV4},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use iroh_tickets::endpoint::EndpointTicket;
+use theopenpresenter_app_lib::{iroh_bridge, IROH_TARGET_ADDR, STORE_FILE};
+use tokio::net::TcpListener;
+
+/// Bundle identifier from tauri.conf.json; names the app data dir
+const APP_IDENTIFIER: &str = "com.theopenpresenter";
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Data dir to read the identity and settings from. Defaults to the
+    /// Studio's own.
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the node id and the ticket the Studio last published
+    Ticket,
+    /// Listen on a local port and pipe every connection to a bridge
+    Connect {
+        /// Ticket of the bridge to connect to
+        ticket: EndpointTicket,
+        /// Local address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+    /// Run the bridge without the app, forwarding to a local port.
+    /// Stop the Studio first, as both would share the same node id.
+    Serve {
+        /// Local TCP address to forward connections to
+        #[arg(long, default_value = IROH_TARGET_ADDR)]
+        target: SocketAddrV4,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let data_dir = match cli.data_dir {
+        Some(data_dir) => data_dir,
+        None => dirs::data_dir()
+            .context("Could not find the app data dir, pass --data-dir")?
+            .join(APP_IDENTIFIER),
+    };
+
+    match cli.command {
+        Command::Ticket => print_ticket(&data_dir),
+        Command::Connect { ticket, listen } => connect(&data_dir, ticket, listen).await,
+        Command::Serve { target } => serve(data_dir, target).await,
+    }
+}
+
+fn print_ticket(data_dir: &Path) -> Result<()> {
+    let node_id = iroh_bridge::read_node_id(data_dir)?
+        .context("No iroh identity in the data dir yet, start the bridge once")?;
+    println!("Node ID: {}", node_id);
+
+    match iroh_bridge::read_ticket(data_dir)? {
+        Some(ticket) => println!("Ticket: {}", ticket),
+        None => println!("Ticket: none published yet"),
+    }
+    Ok(())
+}
+
+async fn connect(data_dir: &Path, ticket: EndpointTicket, listen: SocketAddr) -> Result<()> {
+    let config = iroh_bridge::BridgeConfig::load_from_store(&data_dir.join(STORE_FILE))?;
+    let endpoint = iroh_bridge::client_endpoint(&config).await?;
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+    eprintln!("Forwarding {} to {}", listen, ticket.endpoint_addr().id);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (tcp_stream, peer) = accepted.context("Failed to accept connection")?;
+                let endpoint = endpoint.clone();
+                let remote = ticket.endpoint_addr().clone();
+                tokio::spawn(async move {
+                    if let Err(e) = iroh_bridge::connect_tcp(&endpoint, remote, tcp_stream).await {
+                        tracing::warn!("Connection from {} failed: {:#}", peer, e);
+                    }
+                });
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    endpoint.close().await;
+    Ok(())
+}
+
+async fn serve(data_dir: PathBuf, target: SocketAddrV4) -> Result<()> {
+    let config = iroh_bridge::BridgeConfig::load_from_store(&data_dir.join(STORE_FILE))?;
+    let bridge = iroh_bridge::start_bridge(target, data_dir, config).await?;
+    {
+        let bridge_locked = bridge.lock().await;
+        println!("Node ID: {}", bridge_locked.node_id());
+        println!("Ticket: {}", bridge_locked.ticket());
+    }
+    eprintln!("Forwarding to {}, press Ctrl+C to stop", target);
+
+    tokio::signal::ctrl_c().await?;
+    bridge.lock().await.shutdown().await
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use iroh::{endpoint::Endpoint, EndpointAddr, SecretKey};
+use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    config::{BandwidthConfig, BridgeConfig},
+    connections::ByteCounters,
+    create_endpoint,
+    rate_limit::BandwidthLimits,
+    utils::forward_bidi,
+    ALPN, HANDSHAKE,
+};
+
+/// Bind a throwaway endpoint for dialing bridges. It gets a fresh identity
+/// every time, so it never clashes with the Studio's own node id.
+pub async fn client_endpoint(config: &BridgeConfig) -> Result<Endpoint> {
+    let secret_key = SecretKey::generate(&mut rand::rng());
+    create_endpoint(secret_key, vec![], config).await
+}
+
+/// Pipe a local TCP stream to the bridge at `remote`, the same way
+/// `dumbpipe connect-tcp` would. Returns once either side closes.
+pub async fn connect_tcp(
+    endpoint: &Endpoint,
+    remote: impl Into<EndpointAddr>,
+    tcp_stream: TcpStream,
+) -> Result<()> {
+    let connection = endpoint
+        .connect(remote, ALPN)
+        .await
+        .context("Failed to connect to the bridge")?;
+    let (mut send, recv) = connection
+        .open_bi()
+        .await
+        .context("Error opening bidirectional stream")?;
+    send.write_all(&HANDSHAKE)
+        .await
+        .context("Error sending handshake")?;
+
+    let (tcp_read, tcp_write) = tcp_stream.into_split();
+    let limits = BandwidthLimits::new(&BandwidthConfig::default());
+    forward_bidi(
+        tcp_read,
+        tcp_write,
+        recv,
+        send,
+        Arc::new(ByteCounters::default()),
+        limits.for_connection(),
+        None,
+        CancellationToken::new(),
+    )
+    .await?;
+    Ok(())
+}
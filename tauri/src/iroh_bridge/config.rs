@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use iroh::{RelayMap, RelayMode, RelayUrl};
+use quinn::{IdleTimeout, TransportConfig};
+use std::{fs, io::ErrorKind, path::Path, time::Duration};
+
+/// Name advertised on the LAN when the user hasn't picked one
+const DEFAULT_STUDIO_NAME: &str = "TheOpenPresenter Studio";
+
+/// Key the app stores the `BridgeConfig` under in its settings file
+pub const SETTINGS_KEY: &str = "iroh";
+
+/// User-configurable bridge settings, persisted by the app
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BridgeConfig {
+    pub relay: RelayConfig,
+    /// Advertise the Studio over mDNS so LAN devices can find it
+    pub lan_discovery: bool,
+    /// Friendly name shown to devices discovering the Studio
+    pub studio_name: Option<String>,
+    pub bandwidth: BandwidthConfig,
+    pub timeouts: TimeoutConfig,
+    pub path_policy: PathPolicy,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            relay: RelayConfig::default(),
+            lan_discovery: true,
+            studio_name: None,
+            bandwidth: BandwidthConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            path_policy: PathPolicy::default(),
+        }
+    }
+}
+
+impl BridgeConfig {
+    /// Check the settings can actually be applied to an endpoint
+    pub fn validate(&self) -> Result<()> {
+        self.relay_mode()?;
+        self.timeouts.transport_config()?;
+        Ok(())
+    }
+
+    /// Whether the endpoint is expected to have a home relay
+    pub fn uses_relay(&self) -> bool {
+        self.path_policy != PathPolicy::DirectOnly && self.relay.uses_relay()
+    }
+
+    /// The relay mode to bind the endpoint with. Direct-only bridges get no
+    /// relay at all, so nothing can reach them or fall back through one.
+    pub fn relay_mode(&self) -> Result<RelayMode> {
+        let mode = self.relay.relay_mode()?;
+        if self.path_policy == PathPolicy::DirectOnly {
+            return Ok(RelayMode::Disabled);
+        }
+        Ok(mode)
+    }
+
+    /// Read the settings the app saved to its settings file. A missing file
+    /// or section gives the defaults.
+    pub fn load_from_store(store_path: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(store_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).context("Failed to read settings"),
+        };
+        let mut store: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&contents).context("Invalid settings file")?;
+        match store.remove(SETTINGS_KEY) {
+            Some(value) => serde_json::from_value(value).context("Invalid bridge settings"),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The configured Studio name, or the machine's host name
+    pub fn studio_name(&self) -> String {
+        self.studio_name
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| DEFAULT_STUDIO_NAME.to_string())
+    }
+}
+
+/// Rate limits for forwarded traffic. Each applies to both directions
+/// separately; `None` or `0` means unlimited.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    /// Bytes per second for a single connection
+    pub per_connection_bytes_per_sec: Option<u64>,
+    /// Bytes per second shared by all connections
+    pub global_bytes_per_sec: Option<u64>,
+}
+
+/// How long quiet connections are kept around
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Close a forwarded connection after this long without traffic in
+    /// either direction. `None`, the default, keeps it open for as long as
+    /// both peers do, so a paused show doesn't drop its remotes.
+    pub idle_timeout_secs: Option<u64>,
+    /// How often QUIC pings a quiet connection to keep it alive
+    pub keep_alive_secs: u64,
+    /// How long QUIC waits without hearing from the peer before giving up
+    pub quic_idle_timeout_secs: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: None,
+            keep_alive_secs: 5,
+            quic_idle_timeout_secs: 30,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// QUIC transport settings for the endpoint
+    pub fn transport_config(&self) -> Result<TransportConfig> {
+        if self.keep_alive_secs == 0 || self.keep_alive_secs >= self.quic_idle_timeout_secs {
+            anyhow::bail!("Keep-alive interval must be shorter than the QUIC idle timeout");
+        }
+        let idle_timeout = IdleTimeout::try_from(Duration::from_secs(self.quic_idle_timeout_secs))
+            .context("QUIC idle timeout is too large")?;
+
+        let mut transport = TransportConfig::default();
+        transport
+            .keep_alive_interval(Some(Duration::from_secs(self.keep_alive_secs)))
+            .max_idle_timeout(Some(idle_timeout));
+        Ok(transport)
+    }
+}
+
+/// Which network paths a remote's traffic may take
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathPolicy {
+    /// Forward straight away, through the relay if need be. iroh keeps hole
+    /// punching and moves the connection to a direct path once it finds one.
+    /// Also read from the old `allow-relay` setting, which did the same.
+    #[default]
+    #[serde(alias = "allow-relay")]
+    PreferDirect,
+    /// Bind without a relay and refuse any connection that isn't on a direct
+    /// path, so no traffic ever goes through a relay
+    DirectOnly,
+}
+
+/// Which relays the endpoint uses to reach remotes it can't dial directly
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum RelayConfig {
+    /// iroh's public relays
+    #[default]
+    Default,
+    /// Self-hosted relays only
+    Custom { urls: Vec<String> },
+    /// No relay at all; only remotes on the local network can connect
+    Disabled,
+}
+
+impl RelayConfig {
+    /// Whether the endpoint is expected to have a home relay
+    pub fn uses_relay(&self) -> bool {
+        !matches!(self, RelayConfig::Disabled)
+    }
+
+    /// Resolve into the iroh relay mode, validating custom URLs
+    pub fn relay_mode(&self) -> Result<RelayMode> {
+        match self {
+            RelayConfig::Default => Ok(RelayMode::Default),
+            RelayConfig::Disabled => Ok(RelayMode::Disabled),
+            RelayConfig::Custom { urls } => {
+                if urls.is_empty() {
+                    anyhow::bail!("At least one custom relay URL is required");
+                }
+                let urls = urls
+                    .iter()
+                    .map(|url| {
+                        url.parse::<RelayUrl>()
+                            .with_context(|| format!("Invalid relay URL: {}", url))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(RelayMode::Custom(RelayMap::from_iter(urls)))
+            }
+        }
+    }
+}
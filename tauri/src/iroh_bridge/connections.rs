@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use iroh::{
+    endpoint::{Connection, ConnectionType, Endpoint},
+    Watcher,
+};
+use iroh_base::PublicKey;
+use tokio::sync::broadcast;
+
+use super::utils::unix_millis;
+
+/// Close code sent to a peer we disconnect on purpose
+const DISCONNECT_CODE: u32 = 1;
+
+/// How many connection events we buffer for slow subscribers
+const EVENT_CAPACITY: usize = 64;
+
+/// How often `direct_addr` looks at a path that is still settling
+const PATH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Byte counters shared with the copy tasks in `forward_bidi`
+pub struct ByteCounters {
+    /// Bytes received from the remote and written to the local server
+    pub bytes_in: AtomicU64,
+    /// Bytes read from the local server and sent to the remote
+    pub bytes_out: AtomicU64,
+    /// Monotonic baseline for `last_activity`, so clock changes and
+    /// suspends don't skew the idle timeout
+    created: Instant,
+    /// Milliseconds after `created` of the last byte in either direction
+    last_activity: AtomicU64,
+}
+
+impl Default for ByteCounters {
+    fn default() -> Self {
+        Self {
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            created: Instant::now(),
+            last_activity: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ByteCounters {
+    pub fn add_in(&self, bytes: u64) {
+        self.bytes_in.fetch_add(bytes, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn add_out(&self, bytes: u64) {
+        self.bytes_out.fetch_add(bytes, Ordering::Relaxed);
+        self.touch();
+    }
+
+    fn touch(&self) {
+        let now = self.created.elapsed().as_millis() as u64;
+        self.last_activity.store(now, Ordering::Relaxed);
+    }
+
+    /// How long since any byte moved
+    pub fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_activity.load(Ordering::Relaxed));
+        self.created.elapsed().saturating_sub(last)
+    }
+}
+
+/// How the packets of a connection currently travel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathType {
+    Direct,
+    Relay,
+    Mixed,
+    Unknown,
+}
+
+impl From<ConnectionType> for PathType {
+    fn from(conn_type: ConnectionType) -> Self {
+        match conn_type {
+            ConnectionType::Direct(_) => PathType::Direct,
+            ConnectionType::Relay(_) => PathType::Relay,
+            ConnectionType::Mixed(..) => PathType::Mixed,
+            ConnectionType::None => PathType::Unknown,
+        }
+    }
+}
+
+/// Whether an address belongs to this machine or the network it sits on:
+/// loopback, private and link-local IPv4, and unique-local and link-local
+/// IPv6
+pub fn is_local_network(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_local_network(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+/// Snapshot of a single bridged connection
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub remote_id: String,
+    pub path: PathType,
+    pub rtt_ms: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Unix timestamp in milliseconds
+    pub started_at: u64,
+}
+
+/// Traffic across every connection the bridge has forwarded
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct TrafficStats {
+    pub active_connections: usize,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+/// Emitted whenever a connection enters or leaves the registry
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ConnectionEvent {
+    Opened(ConnectionInfo),
+    Closed(ConnectionInfo),
+}
+
+struct TrackedConnection {
+    remote_id: PublicKey,
+    connection: Connection,
+    started_at: u64,
+    counters: Arc<ByteCounters>,
+}
+
+/// Keeps track of every connection the accept loop has handed off
+#[derive(Clone)]
+pub struct ConnectionRegistry {
+    endpoint: Endpoint,
+    connections: Arc<Mutex<HashMap<u64, TrackedConnection>>>,
+    next_id: Arc<AtomicU64>,
+    events: broadcast::Sender<ConnectionEvent>,
+    /// Bytes moved by connections that have already closed
+    closed_totals: Arc<ByteCounters>,
+}
+
+impl ConnectionRegistry {
+    pub fn new(endpoint: Endpoint) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            endpoint,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+            events,
+            closed_totals: Arc::new(ByteCounters::default()),
+        }
+    }
+
+    /// Add a connection to the registry. It is removed again when the
+    /// returned guard is dropped.
+    pub fn register(&self, connection: Connection) -> ConnectionGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let counters = Arc::new(ByteCounters::default());
+        let tracked = TrackedConnection {
+            remote_id: connection.remote_id(),
+            connection,
+            started_at: unix_millis(),
+            counters: counters.clone(),
+        };
+        let info = self.info(id, &tracked);
+        self.connections.lock().unwrap().insert(id, tracked);
+        let _ = self.events.send(ConnectionEvent::Opened(info));
+
+        ConnectionGuard {
+            registry: self.clone(),
+            id,
+            counters,
+        }
+    }
+
+    /// List all active connections with their current stats
+    pub fn snapshot(&self) -> Vec<ConnectionInfo> {
+        let connections = self.connections.lock().unwrap();
+        let mut infos: Vec<_> = connections
+            .iter()
+            .map(|(id, tracked)| self.info(*id, tracked))
+            .collect();
+        infos.sort_by_key(|info| info.id);
+        infos
+    }
+
+    /// Totals for the lifetime of the bridge, including open connections
+    pub fn traffic(&self) -> TrafficStats {
+        let connections = self.connections.lock().unwrap();
+        let (mut bytes_in, mut bytes_out) = (
+            self.closed_totals.bytes_in.load(Ordering::Relaxed),
+            self.closed_totals.bytes_out.load(Ordering::Relaxed),
+        );
+        for tracked in connections.values() {
+            bytes_in += tracked.counters.bytes_in.load(Ordering::Relaxed);
+            bytes_out += tracked.counters.bytes_out.load(Ordering::Relaxed);
+        }
+        TrafficStats {
+            active_connections: connections.len(),
+            bytes_in,
+            bytes_out,
+        }
+    }
+
+    /// Close every connection from the given peer. Returns how many were closed.
+    pub fn disconnect(&self, remote_id: PublicKey) -> usize {
+        let connections = self.connections.lock().unwrap();
+        let mut closed = 0;
+        for tracked in connections.values() {
+            if tracked.remote_id == remote_id {
+                tracked
+                    .connection
+                    .close(DISCONNECT_CODE.into(), b"disconnected by host");
+                closed += 1;
+            }
+        }
+        closed
+    }
+
+    /// How long since any open connection moved a byte. Zero if none are open.
+    pub fn quiet_for(&self) -> Duration {
+        self.connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|tracked| tracked.counters.idle_for())
+            .min()
+            .unwrap_or_default()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    /// How traffic to the given peer currently travels
+    pub fn path(&self, remote_id: PublicKey) -> PathType {
+        self.endpoint
+            .conn_type(remote_id)
+            .map(|mut watcher| PathType::from(watcher.get()))
+            .unwrap_or(PathType::Unknown)
+    }
+
+    /// The address the given peer reaches us on without a relay. A mixed
+    /// path gets up to `grace` to settle on its direct half.
+    pub async fn direct_addr(&self, remote_id: PublicKey, grace: Duration) -> Option<SocketAddr> {
+        let deadline = Instant::now() + grace;
+        loop {
+            let conn_type = self
+                .endpoint
+                .conn_type(remote_id)
+                .map(|mut watcher| watcher.get());
+            match conn_type {
+                Some(ConnectionType::Direct(addr)) => return Some(addr),
+                Some(ConnectionType::Mixed(..)) if Instant::now() < deadline => {
+                    tokio::time::sleep(PATH_POLL_INTERVAL).await;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn info(&self, id: u64, tracked: &TrackedConnection) -> ConnectionInfo {
+        ConnectionInfo {
+            id,
+            remote_id: tracked.remote_id.to_string(),
+            path: self.path(tracked.remote_id),
+            rtt_ms: tracked.connection.rtt().as_millis() as u64,
+            bytes_in: tracked.counters.bytes_in.load(Ordering::Relaxed),
+            bytes_out: tracked.counters.bytes_out.load(Ordering::Relaxed),
+            started_at: tracked.started_at,
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        let removed = self.connections.lock().unwrap().remove(&id);
+        if let Some(tracked) = removed {
+            let info = self.info(id, &tracked);
+            self.closed_totals
+                .bytes_in
+                .fetch_add(info.bytes_in, Ordering::Relaxed);
+            self.closed_totals
+                .bytes_out
+                .fetch_add(info.bytes_out, Ordering::Relaxed);
+            let _ = self.events.send(ConnectionEvent::Closed(info));
+        }
+    }
+}
+
+/// Removes its connection from the registry when dropped
+pub struct ConnectionGuard {
+    registry: ConnectionRegistry,
+    id: u64,
+    counters: Arc<ByteCounters>,
+}
+
+impl ConnectionGuard {
+    pub fn counters(&self) -> Arc<ByteCounters> {
+        self.counters.clone()
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.registry.remove(self.id);
+    }
+}
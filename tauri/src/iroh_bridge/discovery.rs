@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use futures_lite::StreamExt;
+use iroh::{
+    discovery::{
+        mdns::{DiscoveryEvent, MdnsDiscovery},
+        UserData,
+    },
+    Endpoint,
+};
+use iroh_base::PublicKey;
+use tokio_util::task::AbortOnDropHandle;
+
+use super::utils::unix_millis;
+
+/// Marks our own endpoints among everything else iroh finds on the LAN
+const USER_DATA_PREFIX: &str = "top-studio:";
+
+/// Discovery user data is capped at this many bytes by iroh
+const MAX_USER_DATA_LEN: usize = 245;
+
+/// A Studio seen on the local network
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct DiscoveredStudio {
+    pub node_id: String,
+    pub name: String,
+    pub direct_addresses: Vec<String>,
+    /// Unix timestamp in milliseconds
+    pub last_seen: u64,
+}
+
+/// Advertises this endpoint over mDNS and keeps a list of other Studios
+pub struct LanDiscovery {
+    studios: Arc<Mutex<HashMap<PublicKey, DiscoveredStudio>>>,
+    _task: AbortOnDropHandle<()>,
+}
+
+impl LanDiscovery {
+    /// Start advertising `endpoint` under `name` and browsing for other Studios
+    pub fn start(endpoint: &Endpoint, name: &str) -> Result<Self> {
+        let mdns = MdnsDiscovery::builder()
+            .build(endpoint.id())
+            .context("Failed to start mDNS discovery")?;
+        endpoint.discovery().add(mdns.clone());
+        endpoint.set_user_data_for_discovery(Some(studio_user_data(name)?));
+
+        let studios = Arc::new(Mutex::new(HashMap::new()));
+        let own_id = endpoint.id();
+        let studios_for_task = studios.clone();
+        let task = tokio::spawn(async move {
+            let mut events = mdns.subscribe().await;
+            while let Some(event) = events.next().await {
+                match event {
+                    DiscoveryEvent::Discovered { endpoint_info, .. } => {
+                        let id = endpoint_info.endpoint_id;
+                        if id == own_id {
+                            continue;
+                        }
+                        let Some(name) = endpoint_info
+                            .user_data()
+                            .and_then(|data| data.as_ref().strip_prefix(USER_DATA_PREFIX))
+                        else {
+                            continue;
+                        };
+                        let studio = DiscoveredStudio {
+                            node_id: id.to_string(),
+                            name: name.to_string(),
+                            direct_addresses: endpoint_info
+                                .ip_addrs()
+                                .map(|addr| addr.to_string())
+                                .collect(),
+                            last_seen: unix_millis(),
+                        };
+                        studios_for_task.lock().unwrap().insert(id, studio);
+                    }
+                    DiscoveryEvent::Expired { endpoint_id } => {
+                        studios_for_task.lock().unwrap().remove(&endpoint_id);
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            studios,
+            _task: AbortOnDropHandle::new(task),
+        })
+    }
+
+    /// Studios currently visible on the LAN
+    pub fn studios(&self) -> Vec<DiscoveredStudio> {
+        let mut studios: Vec<_> = self.studios.lock().unwrap().values().cloned().collect();
+        studios.sort_by(|a, b| a.name.cmp(&b.name));
+        studios
+    }
+}
+
+/// Encode a Studio name as discovery user data, truncating it to fit
+fn studio_user_data(name: &str) -> Result<UserData> {
+    let mut data = format!("{}{}", USER_DATA_PREFIX, name);
+    if data.len() > MAX_USER_DATA_LEN {
+        let mut cut = MAX_USER_DATA_LEN;
+        while !data.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        data.truncate(cut);
+    }
+    UserData::try_from(data).context("Invalid Studio name")
+}
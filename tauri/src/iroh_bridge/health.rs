@@ -0,0 +1,31 @@
+/// What happened to the home relay connection since the last check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelayChange {
+    /// Reached the home relay for the first time
+    Connected,
+    /// Reached the home relay again after losing it
+    Reconnected,
+    /// The home relay stopped answering
+    Lost,
+}
+
+/// Follows the home relay connection from one check to the next
+#[derive(Debug, Default)]
+pub struct RelayHealth {
+    /// `None` until the endpoint first reaches its home relay
+    connected: Option<bool>,
+}
+
+impl RelayHealth {
+    /// Record whether the relay is reachable now, and what that changed
+    pub fn update(&mut self, reachable: bool) -> Option<RelayChange> {
+        let change = match (reachable, self.connected) {
+            (true, None) => RelayChange::Connected,
+            (true, Some(false)) => RelayChange::Reconnected,
+            (false, Some(true)) => RelayChange::Lost,
+            _ => return None,
+        };
+        self.connected = Some(reachable);
+        Some(change)
+    }
+}
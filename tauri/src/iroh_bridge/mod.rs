@@ -1,21 +1,53 @@
+mod client;
+mod config;
+mod connections;
+mod discovery;
+mod health;
+mod pairing;
+mod rate_limit;
+mod secret;
+mod ticket;
 mod utils;
 
+#[cfg(test)]
+mod tests;
+
 use anyhow::{Context, Result};
 use iroh::{
     endpoint::{Accepting, Endpoint},
-    SecretKey,
+    SecretKey, Watcher,
 };
 use iroh_base::PublicKey;
 use iroh_tickets::endpoint::EndpointTicket;
-use std::{net::SocketAddrV4, path::PathBuf, sync::Arc, time::Duration};
-use tokio::{
-    net::TcpStream,
-    select,
-    sync::{oneshot, Mutex},
+use std::{
+    net::SocketAddrV4,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
+use tokio::{net::TcpStream, select, sync::Mutex};
+use tokio_util::sync::CancellationToken;
 
 use utils::forward_bidi;
 
+pub use client::{client_endpoint, connect_tcp};
+pub use config::{BridgeConfig, PathPolicy, SETTINGS_KEY};
+pub use connections::{
+    ConnectionEvent, ConnectionInfo, ConnectionRegistry, PathType, TrafficStats,
+};
+pub use discovery::DiscoveredStudio;
+pub use health::{RelayChange, RelayHealth};
+pub use pairing::{request_ticket, ticket_qr_svg, PairingCode};
+pub use secret::{read_node_id, rotate_secret};
+pub use ticket::read_ticket;
+pub(crate) use utils::unix_millis;
+
+use connections::is_local_network;
+use discovery::LanDiscovery;
+use pairing::{PairingCodes, PAIRING_ALPN};
+use rate_limit::BandwidthLimits;
+use secret::get_or_create_secret;
+
 /// Default ALPN protocol for our bridge
 /// Just use Dumbpipe APLN and HANDSHAKE so we can simply use dumbpipe cli
 pub const ALPN: &[u8] = b"DUMBPIPEV0";
@@ -26,12 +58,38 @@ pub const HANDSHAKE: [u8; 5] = *b"hello";
 /// Timeout for waiting for the endpoint to be online
 const ONLINE_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// How long a mixed path gets to turn direct when the connection needs one
+const DIRECT_PATH_GRACE: Duration = Duration::from_secs(2);
+
+/// Close code sent to remotes that try to pair from outside the local network
+const PAIRING_REFUSED_CODE: u32 = 2;
+
+/// Close code sent to remotes that reach a direct-only bridge through a relay
+const RELAYED_REFUSED_CODE: u32 = 3;
+
+/// How long in-flight connections get to finish on shutdown before they are
+/// cut off
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connections that moved no data for this long count as drained
+const DRAIN_QUIET: Duration = Duration::from_millis(500);
+
 /// Holds the state of the iroh bridge
 pub struct IrohBridge {
     endpoint: Endpoint,
-    shutdown_tx: Option<oneshot::Sender<()>>,
+    /// Stops the accept loop
+    stop_accepting: CancellationToken,
+    /// Cuts off every forwarded connection
+    close_connections: CancellationToken,
     ticket: String,
     node_id: PublicKey,
+    data_dir: PathBuf,
+    connections: ConnectionRegistry,
+    config: BridgeConfig,
+    lan_discovery: Option<LanDiscovery>,
+    /// Only handed out while LAN discovery is on
+    pairing: Option<PairingCodes>,
+    started_at: Instant,
 }
 
 impl IrohBridge {
@@ -45,39 +103,162 @@ impl IrohBridge {
         self.node_id
     }
 
-    /// Shutdown the bridge
+    /// The configuration this bridge was started with
+    pub fn config(&self) -> &BridgeConfig {
+        &self.config
+    }
+
+    /// Other Studios seen on the local network, if LAN discovery is on
+    pub fn discovered_studios(&self) -> Option<Vec<DiscoveredStudio>> {
+        self.lan_discovery.as_ref().map(|d| d.studios())
+    }
+
+    /// Create a short code a remote on the LAN can trade for the ticket.
+    /// `None` if LAN discovery is off.
+    pub fn create_pairing_code(&self) -> Option<PairingCode> {
+        self.pairing.as_ref().map(|codes| codes.issue())
+    }
+
+    /// The underlying endpoint, e.g. to dial other Studios
+    pub fn endpoint(&self) -> Endpoint {
+        self.endpoint.clone()
+    }
+
+    /// How long the bridge has been running
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// The home relay the endpoint is reachable through, if any
+    pub fn relay_url(&self) -> Option<String> {
+        self.endpoint
+            .addr()
+            .relay_urls()
+            .next()
+            .map(|url| url.to_string())
+    }
+
+    /// Addresses remotes can dial without going through a relay
+    pub fn direct_addresses(&self) -> Vec<String> {
+        self.endpoint
+            .addr()
+            .ip_addrs()
+            .map(|addr| addr.to_string())
+            .collect()
+    }
+
+    /// Whether the endpoint's home relay answered the latest network report.
+    /// The home relay in the endpoint's address sticks around for minutes
+    /// after the relay goes away, so it can't tell on its own.
+    pub fn has_home_relay(&self) -> bool {
+        let Some(home) = self.endpoint.addr().relay_urls().next().cloned() else {
+            return false;
+        };
+        self.endpoint.net_report().get().is_some_and(|report| {
+            report
+                .relay_latency
+                .iter()
+                .any(|(_, relay_url, _)| *relay_url == home)
+        })
+    }
+
+    /// Rebuild the ticket from the endpoint's current address.
+    /// Returns true if the ticket changed.
+    pub fn refresh_ticket(&mut self) -> bool {
+        let ticket = EndpointTicket::new(self.endpoint.addr()).to_string();
+        if ticket == self.ticket {
+            return false;
+        }
+        self.ticket = ticket;
+        publish_ticket(&self.data_dir, &self.ticket);
+        true
+    }
+
+    /// List the connections currently being forwarded
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.connections.snapshot()
+    }
+
+    /// Subscribe to connections being opened and closed
+    pub fn subscribe_connections(&self) -> tokio::sync::broadcast::Receiver<ConnectionEvent> {
+        self.connections.subscribe()
+    }
+
+    /// Bytes forwarded since the bridge started
+    pub fn traffic(&self) -> TrafficStats {
+        self.connections.traffic()
+    }
+
+    /// Forcibly close every connection from the given peer
+    pub fn disconnect(&self, remote_id: PublicKey) -> usize {
+        self.connections.disconnect(remote_id)
+    }
+
+    /// Shutdown the bridge. New connections are refused straight away,
+    /// while open ones get `DRAIN_TIMEOUT` to finish before being cut off.
     pub async fn shutdown(&mut self) -> Result<()> {
-        if let Some(tx) = self.shutdown_tx.take() {
-            let _ = tx.send(());
+        self.begin_shutdown().drain().await
+    }
+
+    /// Refuse new connections and hand back what's needed to finish the
+    /// shutdown, so callers holding the bridge behind a lock can drop it
+    /// before draining
+    pub fn begin_shutdown(&self) -> BridgeShutdown {
+        self.stop_accepting.cancel();
+        BridgeShutdown {
+            endpoint: self.endpoint.clone(),
+            close_connections: self.close_connections.clone(),
+            connections: self.connections.clone(),
         }
-        self.endpoint.close().await;
-        Ok(())
     }
 }
 
-/// Get or create a secret key for the iroh endpoint
-fn get_or_create_secret(data_dir: &PathBuf) -> Result<SecretKey> {
-    let key_path = data_dir.join("iroh_secret_key");
+/// The rest of a bridge shutdown, detached from the bridge itself
+pub struct BridgeShutdown {
+    endpoint: Endpoint,
+    close_connections: CancellationToken,
+    connections: ConnectionRegistry,
+}
 
-    if key_path.exists() {
-        let key_bytes = std::fs::read(&key_path).context("Failed to read secret key")?;
-        let key_array: [u8; 32] = key_bytes
-            .try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid secret key length"))?;
-        Ok(SecretKey::from_bytes(&key_array))
-    } else {
-        let secret_key = SecretKey::generate(&mut rand::rng());
-        std::fs::create_dir_all(data_dir).context("Failed to create data directory")?;
-        std::fs::write(&key_path, secret_key.to_bytes()).context("Failed to write secret key")?;
-        Ok(secret_key)
+impl BridgeShutdown {
+    /// Wait for open connections to finish, then cut off the rest and close
+    /// the endpoint. Connections that go quiet for `DRAIN_QUIET` are not
+    /// waited on, so idle websockets don't hold up every stop.
+    pub async fn drain(self) -> Result<()> {
+        let deadline = Instant::now() + DRAIN_TIMEOUT;
+        while Instant::now() < deadline {
+            let active = self.connections.traffic().active_connections;
+            if active == 0 || self.connections.quiet_for() >= DRAIN_QUIET {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let remaining = self.connections.traffic().active_connections;
+        if remaining > 0 {
+            tracing::info!("Closing {} connections still open after drain", remaining);
+        }
+        self.close_connections.cancel();
+        self.endpoint.close().await;
+        Ok(())
     }
 }
 
 /// Create an iroh endpoint
-async fn create_endpoint(secret_key: SecretKey, alpn: Vec<u8>) -> Result<Endpoint> {
-    let endpoint = Endpoint::builder()
+async fn create_endpoint(
+    secret_key: SecretKey,
+    alpns: Vec<Vec<u8>>,
+    config: &BridgeConfig,
+) -> Result<Endpoint> {
+    let builder = Endpoint::builder()
         .secret_key(secret_key)
-        .alpns(vec![alpn])
+        .alpns(alpns)
+        .relay_mode(config.relay_mode()?)
+        .transport_config(config.timeouts.transport_config()?);
+    // The tests run their own relay with a self-signed certificate
+    #[cfg(test)]
+    let builder = builder.insecure_skip_relay_cert_verify(true);
+    let endpoint = builder
         .bind()
         .await
         .context("Failed to create iroh endpoint")?;
@@ -85,13 +266,68 @@ async fn create_endpoint(secret_key: SecretKey, alpn: Vec<u8>) -> Result<Endpoin
     Ok(endpoint)
 }
 
+/// Everything a connection handler needs from the bridge
+#[derive(Clone)]
+struct ConnectionContext {
+    endpoint: Endpoint,
+    target_addr: SocketAddrV4,
+    registry: ConnectionRegistry,
+    bandwidth: Arc<BandwidthLimits>,
+    idle_timeout: Option<Duration>,
+    path_policy: PathPolicy,
+    close_connections: CancellationToken,
+    pairing: Option<PairingCodes>,
+}
+
 /// Handle an incoming iroh connection
-async fn handle_connection(accepting: Accepting, target_addr: SocketAddrV4) -> Result<()> {
+async fn handle_connection(mut accepting: Accepting, ctx: ConnectionContext) -> Result<()> {
+    let target_addr = ctx.target_addr;
+    let alpn = accepting.alpn().await.context("Error reading ALPN")?;
     let connection = accepting.await.context("Error accepting connection")?;
     let remote_id = connection.remote_id();
+
+    if alpn == PAIRING_ALPN {
+        let Some(codes) = ctx.pairing else {
+            anyhow::bail!("Pairing request from {} while pairing is off", remote_id);
+        };
+        // Codes are read off the Studio's screen, so they are only good for
+        // remotes in the same building
+        let direct = ctx.registry.direct_addr(remote_id, DIRECT_PATH_GRACE).await;
+        if !direct.is_some_and(|addr| is_local_network(addr.ip())) {
+            connection.close(
+                PAIRING_REFUSED_CODE.into(),
+                b"pairing needs a local network connection",
+            );
+            anyhow::bail!(
+                "Refused pairing from {}: not on the local network ({:?})",
+                remote_id,
+                direct
+            );
+        }
+        return pairing::serve(connection, ctx.endpoint, codes).await;
+    }
     tracing::info!("Got connection from {}", remote_id);
 
-    let (mut send, mut recv) = connection
+    // A direct-only endpoint has no relay to begin with; this also holds if a
+    // remote still manages to route through one
+    if ctx.path_policy == PathPolicy::DirectOnly
+        && ctx
+            .registry
+            .direct_addr(remote_id, DIRECT_PATH_GRACE)
+            .await
+            .is_none()
+    {
+        connection.close(
+            RELAYED_REFUSED_CODE.into(),
+            b"this bridge only accepts direct connections",
+        );
+        anyhow::bail!("Refused {}: not a direct connection", remote_id);
+    }
+
+    // Stays registered until this function returns
+    let tracked = ctx.registry.register(connection.clone());
+
+    let (send, mut recv) = connection
         .accept_bi()
         .await
         .context("Error accepting bidirectional stream")?;
@@ -108,6 +344,9 @@ async fn handle_connection(accepting: Accepting, target_addr: SocketAddrV4) -> R
         anyhow::bail!("Invalid handshake received");
     }
 
+    let path = ctx.registry.path(remote_id);
+    tracing::info!("Forwarding for {} over a {:?} path", remote_id, path);
+
     // Connect to local TCP server
     let tcp_stream = TcpStream::connect(target_addr).await.context(format!(
         "Error connecting to local server at {}",
@@ -117,22 +356,49 @@ async fn handle_connection(accepting: Accepting, target_addr: SocketAddrV4) -> R
     tracing::info!("Connected to local server at {}", target_addr);
 
     let (tcp_read, tcp_write) = tcp_stream.into_split();
-    forward_bidi(tcp_read, tcp_write, recv, send).await?;
+    forward_bidi(
+        tcp_read,
+        tcp_write,
+        recv,
+        send,
+        tracked.counters(),
+        ctx.bandwidth.for_connection(),
+        ctx.idle_timeout,
+        ctx.close_connections.child_token(),
+    )
+    .await?;
 
     tracing::info!("Connection from {} closed", remote_id);
     Ok(())
 }
 
+/// Write the ticket to the data dir for the CLI. Not fatal if it fails.
+fn publish_ticket(data_dir: &Path, ticket: &str) {
+    if let Err(e) = ticket::save_ticket(data_dir, ticket) {
+        tracing::warn!("Failed to save the ticket: {:#}", e);
+    }
+}
+
 /// Start the iroh bridge that forwards connections to a local TCP address
 pub async fn start_bridge(
     target_addr: SocketAddrV4,
     data_dir: PathBuf,
+    config: BridgeConfig,
 ) -> Result<Arc<Mutex<IrohBridge>>> {
     let secret_key = get_or_create_secret(&data_dir)?;
-    let endpoint = create_endpoint(secret_key, ALPN.to_vec()).await?;
+    // Pairing goes with LAN discovery. Remotes outside the local network are
+    // refused in `handle_connection`.
+    let mut alpns = vec![ALPN.to_vec()];
+    if config.lan_discovery {
+        alpns.push(PAIRING_ALPN.to_vec());
+    }
+    let endpoint = create_endpoint(secret_key, alpns, &config).await?;
 
-    // Wait for the endpoint to be online
-    if (tokio::time::timeout(ONLINE_TIMEOUT, endpoint.online()).await).is_err() {
+    // Wait for the endpoint to be online. Without a relay there is no home
+    // relay to wait for.
+    if config.uses_relay()
+        && (tokio::time::timeout(ONLINE_TIMEOUT, endpoint.online()).await).is_err()
+    {
         tracing::warn!("Warning: Failed to connect to home relay within timeout");
     }
 
@@ -150,14 +416,52 @@ pub async fn start_bridge(
     if let Some(relay_url) = addr.relay_urls().next() {
         tracing::info!("Relay URL: {}", relay_url);
     }
-
-    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+    publish_ticket(&data_dir, &ticket_string);
+
+    // LAN discovery is a convenience; the bridge still works without it
+    let lan_discovery = if config.lan_discovery {
+        let name = config.studio_name();
+        match LanDiscovery::start(&endpoint, &name) {
+            Ok(discovery) => {
+                tracing::info!("Advertising on the local network as {}", name);
+                Some(discovery)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to start LAN discovery: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let stop_accepting = CancellationToken::new();
+    let close_connections = CancellationToken::new();
+    let registry = ConnectionRegistry::new(endpoint.clone());
+    let pairing = config.lan_discovery.then(PairingCodes::default);
+    let ctx = ConnectionContext {
+        endpoint: endpoint.clone(),
+        target_addr,
+        registry: registry.clone(),
+        bandwidth: Arc::new(BandwidthLimits::new(&config.bandwidth)),
+        idle_timeout: config.timeouts.idle_timeout(),
+        path_policy: config.path_policy,
+        close_connections: close_connections.clone(),
+        pairing: pairing.clone(),
+    };
 
     let bridge = Arc::new(Mutex::new(IrohBridge {
         endpoint: endpoint.clone(),
-        shutdown_tx: Some(shutdown_tx),
+        stop_accepting: stop_accepting.clone(),
+        close_connections,
         ticket: ticket_string,
         node_id,
+        data_dir,
+        connections: registry,
+        config,
+        lan_discovery,
+        pairing,
+        started_at: Instant::now(),
     }));
 
     // Spawn the accept loop
@@ -178,13 +482,14 @@ pub async fn start_bridge(
                         }
                     };
 
+                    let ctx = ctx.clone();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(accepting, target_addr).await {
+                        if let Err(e) = handle_connection(accepting, ctx).await {
                             tracing::warn!("Error handling connection: {}", e);
                         }
                     });
                 }
-                _ = &mut shutdown_rx => {
+                _ = stop_accepting.cancelled() => {
                     tracing::info!("Shutdown signal received, stopping accept loop");
                     break;
                 }
@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use iroh::{
+    endpoint::{Connection, Endpoint},
+    EndpointAddr,
+};
+use iroh_base::PublicKey;
+use iroh_tickets::endpoint::EndpointTicket;
+use qrcode::{render::svg, EcLevel, QrCode};
+use rand::Rng;
+
+/// ALPN a remote speaks to swap a pairing code for the full ticket
+pub const PAIRING_ALPN: &[u8] = b"top-pairing/0";
+
+/// How long a pairing code can be redeemed for
+const CODE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Number of digits in a pairing code
+const CODE_LEN: usize = 6;
+
+/// Wrong guesses one remote may make before it is locked out
+const MAX_FAILED_ATTEMPTS: u32 = 10;
+
+/// How long a remote that made too many wrong guesses is locked out
+const LOCKOUT: Duration = CODE_TTL;
+
+/// Wrong guesses from all remotes together before every outstanding code is
+/// revoked. New node ids cost nothing, so the per-remote limit alone doesn't
+/// stop anyone from working through all million codes.
+const MAX_TOTAL_FAILED_ATTEMPTS: u32 = 30;
+
+/// Longest request or response we read on a pairing stream
+const MAX_MESSAGE_LEN: usize = 4096;
+
+/// How long a pairing exchange may take end to end
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Close code sent once the exchange is over
+const DONE_CODE: u32 = 0;
+
+const OK_PREFIX: &str = "ok:";
+
+/// A short code a remote on the LAN can trade for the ticket
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct PairingCode {
+    pub code: String,
+    /// Unix timestamp in milliseconds
+    pub expires_at: u64,
+}
+
+#[derive(Default)]
+struct CodeBook {
+    codes: HashMap<String, Instant>,
+    /// Wrong guesses per remote, with the time of the latest one
+    failed_attempts: HashMap<PublicKey, (u32, Instant)>,
+    /// Wrong guesses from anyone against the outstanding codes
+    total_failed: u32,
+}
+
+/// Outstanding pairing codes. Each one can be redeemed once.
+#[derive(Clone, Default)]
+pub struct PairingCodes {
+    inner: Arc<Mutex<CodeBook>>,
+}
+
+impl PairingCodes {
+    /// Create a new code, valid for `CODE_TTL`
+    pub fn issue(&self) -> PairingCode {
+        let mut book = self.inner.lock().unwrap();
+        book.codes.retain(|_, expires| *expires > Instant::now());
+
+        let mut rng = rand::rng();
+        let code = loop {
+            let code = format!(
+                "{:0width$}",
+                rng.random_range(0..10u32.pow(CODE_LEN as u32)),
+                width = CODE_LEN
+            );
+            if !book.codes.contains_key(&code) {
+                break code;
+            }
+        };
+        book.codes.insert(code.clone(), Instant::now() + CODE_TTL);
+
+        PairingCode {
+            code,
+            expires_at: super::unix_millis() + CODE_TTL.as_millis() as u64,
+        }
+    }
+
+    /// Use up a code on behalf of `remote_id`. Returns false if the code is
+    /// unknown or expired, or if the remote is locked out. Too many wrong
+    /// guesses overall revoke every outstanding code.
+    pub fn redeem(&self, remote_id: PublicKey, code: &str) -> bool {
+        let mut book = self.inner.lock().unwrap();
+        let now = Instant::now();
+        book.codes.retain(|_, expires| *expires > now);
+        if book.codes.is_empty() {
+            // Fresh codes get a fresh budget
+            book.total_failed = 0;
+        }
+        book.failed_attempts
+            .retain(|_, (_, last)| now.duration_since(*last) < LOCKOUT);
+
+        // Six digits are easy to guess given enough tries
+        let failed = book.failed_attempts.get(&remote_id).map_or(0, |(n, _)| *n);
+        if failed >= MAX_FAILED_ATTEMPTS {
+            tracing::warn!("Ignoring pairing code from locked out {}", remote_id);
+            return false;
+        }
+        if book.codes.remove(code).is_some() {
+            book.failed_attempts.remove(&remote_id);
+            return true;
+        }
+        book.failed_attempts.insert(remote_id, (failed + 1, now));
+        book.total_failed += 1;
+        if book.total_failed >= MAX_TOTAL_FAILED_ATTEMPTS && !book.codes.is_empty() {
+            tracing::warn!(
+                "{} wrong pairing codes, revoking {} outstanding code(s)",
+                book.total_failed,
+                book.codes.len()
+            );
+            book.codes.clear();
+            book.total_failed = 0;
+        }
+        false
+    }
+}
+
+/// Answer a pairing request with the current ticket if the code is valid
+pub async fn serve(connection: Connection, endpoint: Endpoint, codes: PairingCodes) -> Result<()> {
+    let exchange = async {
+        let (mut send, mut recv) = connection
+            .accept_bi()
+            .await
+            .context("Error accepting pairing stream")?;
+        let request = recv
+            .read_to_end(MAX_MESSAGE_LEN)
+            .await
+            .context("Error reading pairing code")?;
+        let code = String::from_utf8_lossy(&request);
+
+        let response = if codes.redeem(connection.remote_id(), code.trim()) {
+            tracing::info!("Paired with {}", connection.remote_id());
+            format!("{}{}", OK_PREFIX, EndpointTicket::new(endpoint.addr()))
+        } else {
+            tracing::warn!("Rejected pairing code from {}", connection.remote_id());
+            "invalid or expired pairing code".to_string()
+        };
+        send.write_all(response.as_bytes())
+            .await
+            .context("Error sending pairing response")?;
+        send.finish()?;
+        // Wait for the remote to read the response and hang up
+        connection.closed().await;
+        anyhow::Ok(())
+    };
+
+    tokio::time::timeout(EXCHANGE_TIMEOUT, exchange)
+        .await
+        .context("Pairing timed out")?
+}
+
+/// Ask a Studio for its ticket in exchange for `code`. A bare node id is
+/// enough for Studios found through LAN discovery.
+pub async fn request_ticket(
+    endpoint: &Endpoint,
+    remote: impl Into<EndpointAddr>,
+    code: &str,
+) -> Result<String> {
+    let remote = remote.into();
+    let exchange = async {
+        let connection = endpoint
+            .connect(remote, PAIRING_ALPN)
+            .await
+            .context("Failed to reach the Studio")?;
+        let (mut send, mut recv) = connection.open_bi().await?;
+        send.write_all(code.trim().as_bytes()).await?;
+        send.finish()?;
+        let response = recv
+            .read_to_end(MAX_MESSAGE_LEN)
+            .await
+            .context("Error reading pairing response")?;
+        connection.close(DONE_CODE.into(), b"paired");
+
+        let response = String::from_utf8(response).context("Invalid pairing response")?;
+        let Some(ticket) = response.strip_prefix(OK_PREFIX) else {
+            anyhow::bail!("Pairing refused: {}", response);
+        };
+        // Make sure we hand back something usable
+        ticket
+            .parse::<EndpointTicket>()
+            .context("Studio sent an invalid ticket")?;
+        anyhow::Ok(ticket.to_string())
+    };
+
+    tokio::time::timeout(EXCHANGE_TIMEOUT, exchange)
+        .await
+        .context("Pairing timed out")?
+}
+
+/// Render a ticket as an SVG QR code
+pub fn ticket_qr_svg(ticket: &str) -> Result<String> {
+    // Tickets are long, so keep the error correction low to stay scannable
+    let code = QrCode::with_error_correction_level(ticket, EcLevel::L)
+        .context("Ticket is too long for a QR code")?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(256, 256)
+        .dark_color(svg::Color("#000000"))
+        .light_color(svg::Color("#ffffff"))
+        .build())
+}
@@ -0,0 +1,112 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::Mutex, time::Instant};
+
+use super::config::BandwidthConfig;
+
+/// Token bucket shared by everything that sends through it.
+///
+/// Waiters queue on a FIFO mutex, so when several connections share one
+/// limiter they take turns chunk by chunk instead of one starving the rest.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Can go negative: a chunk larger than the burst is let through and
+    /// paid off by the next caller's wait.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_sec as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until `bytes` may be sent
+    pub async fn acquire(&self, bytes: usize) {
+        let rate = self.bytes_per_sec as f64;
+        // Held across the sleep so later callers queue behind us
+        let mut bucket = self.bucket.lock().await;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        // Allow at most one second of burst
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.last_refill = now;
+
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens < 0.0 {
+            let wait = Duration::from_secs_f64(-bucket.tokens / rate);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// The limiters that apply to one direction of one connection
+#[derive(Clone, Default)]
+pub struct DirectionLimits {
+    connection: Option<Arc<RateLimiter>>,
+    global: Option<Arc<RateLimiter>>,
+}
+
+impl DirectionLimits {
+    /// Wait until `bytes` fit within both the connection and the global limit
+    pub async fn acquire(&self, bytes: usize) {
+        if let Some(limiter) = &self.connection {
+            limiter.acquire(bytes).await;
+        }
+        if let Some(limiter) = &self.global {
+            limiter.acquire(bytes).await;
+        }
+    }
+}
+
+/// Bridge-wide bandwidth limits, handing out per-connection limiters
+pub struct BandwidthLimits {
+    per_connection: Option<u64>,
+    global_upload: Option<Arc<RateLimiter>>,
+    global_download: Option<Arc<RateLimiter>>,
+}
+
+impl BandwidthLimits {
+    pub fn new(config: &BandwidthConfig) -> Self {
+        let global = || {
+            config
+                .global_bytes_per_sec
+                .filter(|rate| *rate > 0)
+                .map(|rate| Arc::new(RateLimiter::new(rate)))
+        };
+        Self {
+            per_connection: config.per_connection_bytes_per_sec.filter(|rate| *rate > 0),
+            global_upload: global(),
+            global_download: global(),
+        }
+    }
+
+    /// Limits for a new connection as `(to remote, from remote)`
+    pub fn for_connection(&self) -> (DirectionLimits, DirectionLimits) {
+        let connection = || {
+            self.per_connection
+                .map(|rate| Arc::new(RateLimiter::new(rate)))
+        };
+        (
+            DirectionLimits {
+                connection: connection(),
+                global: self.global_upload.clone(),
+            },
+            DirectionLimits {
+                connection: connection(),
+                global: self.global_download.clone(),
+            },
+        )
+    }
+}
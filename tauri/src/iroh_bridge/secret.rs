@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use iroh::SecretKey;
+use iroh_base::PublicKey;
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use super::utils::unix_millis;
+
+/// File in the data dir holding the raw 32 byte secret key
+const KEY_FILE: &str = "iroh_secret_key";
+
+fn key_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(KEY_FILE)
+}
+
+/// Get or create a secret key for the iroh endpoint.
+///
+/// A key file with the wrong length is moved aside and replaced rather than
+/// failing the bridge, at the cost of a new node id. Failing to read the file
+/// is an error, so a transient I/O problem never throws the identity away.
+pub fn get_or_create_secret(data_dir: &Path) -> Result<SecretKey> {
+    let key_path = key_path(data_dir);
+
+    match fs::read(&key_path) {
+        Ok(key_bytes) => match parse_secret(key_bytes) {
+            Ok(secret_key) => {
+                restrict_permissions(&key_path)?;
+                return Ok(secret_key);
+            }
+            Err(e) => {
+                let backup = data_dir.join(format!("{}.corrupt-{}", KEY_FILE, unix_millis()));
+                tracing::warn!(
+                    "Unusable iroh secret key ({:#}), moving it to {} and generating a new one",
+                    e,
+                    backup.display()
+                );
+                fs::rename(&key_path, &backup).context("Failed to move corrupt secret key")?;
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context("Failed to read secret key"),
+    }
+
+    let secret_key = SecretKey::generate(&mut rand::rng());
+    write_secret(data_dir, &secret_key)?;
+    Ok(secret_key)
+}
+
+/// Replace the secret key with a fresh one, giving the endpoint a new node id
+pub fn rotate_secret(data_dir: &Path) -> Result<SecretKey> {
+    let secret_key = SecretKey::generate(&mut rand::rng());
+    write_secret(data_dir, &secret_key)?;
+    tracing::info!(
+        "Rotated iroh secret key, new node ID: {}",
+        secret_key.public()
+    );
+    Ok(secret_key)
+}
+
+/// The node id of the stored key, without creating one if there is none
+pub fn read_node_id(data_dir: &Path) -> Result<Option<PublicKey>> {
+    let key_path = key_path(data_dir);
+    if !key_path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(read_secret(&key_path)?.public()))
+}
+
+fn read_secret(key_path: &Path) -> Result<SecretKey> {
+    parse_secret(fs::read(key_path).context("Failed to read secret key")?)
+}
+
+fn parse_secret(key_bytes: Vec<u8>) -> Result<SecretKey> {
+    let key_array: [u8; 32] = key_bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| anyhow::anyhow!("Invalid secret key length {}", bytes.len()))?;
+    Ok(SecretKey::from_bytes(&key_array))
+}
+
+/// Tighten a key file written by an older version, which may be readable by
+/// other users
+fn restrict_permissions(key_path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(key_path)
+            .context("Failed to read secret key permissions")?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            fs::set_permissions(key_path, fs::Permissions::from_mode(0o600))
+                .context("Failed to restrict secret key permissions")?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = key_path;
+    Ok(())
+}
+
+/// Write the key to a temporary file readable only by us, then rename it over
+/// the old one so a crash never leaves a half-written key behind.
+fn write_secret(data_dir: &Path, secret_key: &SecretKey) -> Result<()> {
+    fs::create_dir_all(data_dir).context("Failed to create data directory")?;
+    let key_path = key_path(data_dir);
+    let tmp_path = data_dir.join(format!("{}.tmp", KEY_FILE));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(&tmp_path)
+        .context("Failed to create secret key file")?;
+    file.write_all(&secret_key.to_bytes())
+        .context("Failed to write secret key")?;
+    file.sync_all().context("Failed to flush secret key")?;
+    drop(file);
+
+    fs::rename(&tmp_path, &key_path).context("Failed to replace secret key")?;
+    Ok(())
+}
@@ -0,0 +1,828 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use iroh::{
+    endpoint::{Connection, Endpoint, PathSelection, RecvStream, SendStream},
+    EndpointAddr, RelayMap, RelayMode, RelayUrl,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::Mutex,
+};
+
+use super::config::RelayConfig;
+use super::*;
+
+/// Upper bound for anything that should happen promptly on localhost
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Relays and mDNS off, so everything stays on this machine
+fn local_config() -> BridgeConfig {
+    BridgeConfig {
+        relay: RelayConfig::Disabled,
+        lan_discovery: false,
+        ..Default::default()
+    }
+}
+
+/// TCP server that writes back whatever it receives
+async fn echo_server() -> SocketAddrV4 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let SocketAddr::V4(addr) = listener.local_addr().unwrap() else {
+        unreachable!("bound to an IPv4 address");
+    };
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut read, mut write) = stream.split();
+                let _ = tokio::io::copy(&mut read, &mut write).await;
+            });
+        }
+    });
+    addr
+}
+
+async fn start_local_bridge(data_dir: &Path) -> Arc<Mutex<IrohBridge>> {
+    let target = echo_server().await;
+    start_bridge(target, data_dir.to_path_buf(), local_config())
+        .await
+        .unwrap()
+}
+
+async fn client_endpoint() -> Endpoint {
+    Endpoint::builder()
+        .relay_mode(RelayMode::Disabled)
+        .bind()
+        .await
+        .unwrap()
+}
+
+/// Client homed on the given relay that never hole punches itself, so its
+/// connections start out relayed. The relay uses a self-signed certificate.
+async fn relay_client_endpoint(relay_map: RelayMap) -> Endpoint {
+    Endpoint::builder()
+        .relay_mode(RelayMode::Custom(relay_map))
+        .insecure_skip_relay_cert_verify(true)
+        .path_selection(PathSelection::RelayOnly)
+        .bind()
+        .await
+        .unwrap()
+}
+
+/// Bridge settings that home the bridge on the given relay
+fn relay_config(relay_url: &RelayUrl) -> BridgeConfig {
+    BridgeConfig {
+        relay: RelayConfig::Custom {
+            urls: vec![relay_url.to_string()],
+        },
+        ..local_config()
+    }
+}
+
+/// Poll the bridge like the supervisor does until the relay health changes.
+/// The endpoint only probes its relay every 20 to 26 seconds.
+async fn wait_for_relay_change(
+    bridge: &Arc<Mutex<IrohBridge>>,
+    health: &mut RelayHealth,
+) -> RelayChange {
+    let poll = async {
+        loop {
+            let reachable = bridge.lock().await.has_home_relay();
+            if let Some(change) = health.update(reachable) {
+                return change;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(60), poll)
+        .await
+        .expect("relay health didn't change")
+}
+
+/// Connect to the bridge and send `handshake` on a fresh bidi stream
+async fn open_pipe(
+    client: &Endpoint,
+    bridge: &Arc<Mutex<IrohBridge>>,
+    handshake: &[u8],
+) -> (Connection, SendStream, RecvStream) {
+    let addr = bridge.lock().await.endpoint.addr();
+    open_pipe_to(client, addr, handshake).await
+}
+
+/// Like `open_pipe`, dialing the bridge at `addr`
+async fn open_pipe_to(
+    client: &Endpoint,
+    addr: EndpointAddr,
+    handshake: &[u8],
+) -> (Connection, SendStream, RecvStream) {
+    let connection = tokio::time::timeout(STEP_TIMEOUT, client.connect(addr, ALPN))
+        .await
+        .expect("connect timed out")
+        .unwrap();
+    let (mut send, recv) = connection.open_bi().await.unwrap();
+    send.write_all(handshake).await.unwrap();
+    (connection, send, recv)
+}
+
+/// Send `payload` through the pipe and collect everything echoed back
+async fn echo(mut send: SendStream, mut recv: RecvStream, payload: Vec<u8>) -> Vec<u8> {
+    let len = payload.len();
+    // Write and read at the same time, the echo applies backpressure
+    let writer = async move {
+        send.write_all(&payload).await.unwrap();
+        send.finish().unwrap();
+    };
+    let reader = async move { recv.read_to_end(len + 1).await.unwrap() };
+    let ((), echoed) = tokio::time::timeout(STEP_TIMEOUT, async { tokio::join!(writer, reader) })
+        .await
+        .expect("echo timed out");
+    echoed
+}
+
+fn payload(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31) ^ seed)
+        .collect()
+}
+
+#[tokio::test]
+async fn forwards_to_local_server() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    let client = client_endpoint().await;
+
+    let (_connection, send, recv) = open_pipe(&client, &bridge, &HANDSHAKE).await;
+    let echoed = echo(send, recv, b"hello bridge".to_vec()).await;
+
+    assert_eq!(echoed, b"hello bridge");
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn rejects_invalid_handshake() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    let client = client_endpoint().await;
+
+    let (_connection, mut send, mut recv) = open_pipe(&client, &bridge, b"nope!").await;
+    let _ = send.write_all(b"should never reach the server").await;
+    let _ = send.finish();
+
+    let result = tokio::time::timeout(STEP_TIMEOUT, recv.read_to_end(1024))
+        .await
+        .expect("rejected connection was left open");
+    if let Ok(data) = result {
+        assert!(data.is_empty(), "got data without a valid handshake");
+    }
+    assert_eq!(bridge.lock().await.traffic().bytes_in, 0);
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn transfers_large_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    let client = client_endpoint().await;
+
+    let data = payload(8 * 1024 * 1024, 7);
+    let (_connection, send, recv) = open_pipe(&client, &bridge, &HANDSHAKE).await;
+    let echoed = echo(send, recv, data.clone()).await;
+
+    assert_eq!(echoed.len(), data.len());
+    assert!(echoed == data, "echoed payload differs");
+
+    let traffic = bridge.lock().await.traffic();
+    assert_eq!(traffic.bytes_in, data.len() as u64);
+    assert_eq!(traffic.bytes_out, data.len() as u64);
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn handles_concurrent_connections() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+
+    let mut tasks = Vec::new();
+    for seed in 0..8u8 {
+        let bridge = bridge.clone();
+        tasks.push(tokio::spawn(async move {
+            let client = client_endpoint().await;
+            let data = payload(256 * 1024, seed);
+            let (_connection, send, recv) = open_pipe(&client, &bridge, &HANDSHAKE).await;
+            let echoed = echo(send, recv, data.clone()).await;
+            assert!(echoed == data, "connection {} got another's data", seed);
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn disconnecting_a_remote_closes_it_and_reports_the_events() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    let mut events = bridge.lock().await.subscribe_connections();
+    let client = client_endpoint().await;
+
+    let (connection, send, recv) = open_pipe(&client, &bridge, &HANDSHAKE).await;
+    // Keeps the stream open, so only the disconnect can end it
+    let _echoed = tokio::spawn(echo(send, recv, b"still here".to_vec()));
+    let opened = tokio::time::timeout(STEP_TIMEOUT, events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let ConnectionEvent::Opened(info) = opened else {
+        panic!("expected an opened event, got {:?}", opened);
+    };
+    assert_eq!(info.remote_id, client.id().to_string());
+
+    assert_eq!(bridge.lock().await.disconnect(client.id()), 1);
+
+    let reason = tokio::time::timeout(STEP_TIMEOUT, connection.closed())
+        .await
+        .expect("disconnected remote was left open");
+    assert!(
+        matches!(
+            reason,
+            iroh::endpoint::ConnectionError::ApplicationClosed(_)
+        ),
+        "closed with {:?}",
+        reason
+    );
+    let closed = tokio::time::timeout(STEP_TIMEOUT, events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let ConnectionEvent::Closed(closed_info) = closed else {
+        panic!("expected a closed event, got {:?}", closed);
+    };
+    assert_eq!(closed_info.id, info.id);
+    assert!(bridge.lock().await.connections().is_empty());
+    // Nothing left to disconnect
+    assert_eq!(bridge.lock().await.disconnect(client.id()), 0);
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_closes_in_flight_connections() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    let client = client_endpoint().await;
+
+    // Leave the stream open so the bridge has to cut it off
+    let (_connection, mut send, mut recv) = open_pipe(&client, &bridge, &HANDSHAKE).await;
+    send.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    tokio::time::timeout(STEP_TIMEOUT, recv.read_exact(&mut buf))
+        .await
+        .expect("echo timed out")
+        .unwrap();
+    assert_eq!(bridge.lock().await.connections().len(), 1);
+
+    tokio::time::timeout(DRAIN_TIMEOUT + STEP_TIMEOUT, async {
+        bridge.lock().await.shutdown().await.unwrap();
+    })
+    .await
+    .expect("shutdown did not finish after the drain timeout");
+
+    let result = tokio::time::timeout(STEP_TIMEOUT, recv.read_to_end(1024))
+        .await
+        .expect("connection survived shutdown");
+    assert!(result.map(|data| data.is_empty()).unwrap_or(true));
+}
+
+#[tokio::test]
+async fn draining_leaves_the_bridge_unlocked_and_skips_quiet_connections() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    let client = client_endpoint().await;
+
+    let (_connection, mut send, mut recv) = open_pipe(&client, &bridge, &HANDSHAKE).await;
+    send.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    tokio::time::timeout(STEP_TIMEOUT, recv.read_exact(&mut buf))
+        .await
+        .expect("echo timed out")
+        .unwrap();
+
+    let shutdown = bridge.lock().await.begin_shutdown();
+    let drain = tokio::spawn(shutdown.drain());
+    // Status reads keep working while connections drain
+    let locked = tokio::time::timeout(Duration::from_millis(100), bridge.lock()).await;
+    assert!(locked.is_ok(), "bridge stayed locked during the drain");
+
+    tokio::time::timeout(DRAIN_TIMEOUT / 2, drain)
+        .await
+        .expect("drain waited on a quiet connection")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn keeps_node_id_across_restarts() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = echo_server().await;
+
+    let first = start_bridge(target, dir.path().to_path_buf(), local_config())
+        .await
+        .unwrap();
+    let first_id = first.lock().await.node_id();
+    first.lock().await.shutdown().await.unwrap();
+
+    let second = start_bridge(target, dir.path().to_path_buf(), local_config())
+        .await
+        .unwrap();
+    assert_eq!(second.lock().await.node_id(), first_id);
+    second.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn recovers_from_corrupt_secret_key() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("iroh_secret_key"), b"too short").unwrap();
+
+    let bridge = start_local_bridge(dir.path()).await;
+    bridge.lock().await.shutdown().await.unwrap();
+
+    let key = std::fs::read(dir.path().join("iroh_secret_key")).unwrap();
+    assert_eq!(key.len(), 32);
+    let backups = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().contains(".corrupt-"))
+        .count();
+    assert_eq!(backups, 1);
+}
+
+#[tokio::test]
+async fn rotating_the_secret_changes_node_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = echo_server().await;
+
+    let before = start_bridge(target, dir.path().to_path_buf(), local_config())
+        .await
+        .unwrap();
+    let before_id = before.lock().await.node_id();
+    before.lock().await.shutdown().await.unwrap();
+
+    let rotated = rotate_secret(dir.path()).unwrap();
+    assert_ne!(rotated.public(), before_id);
+
+    let after = start_bridge(target, dir.path().to_path_buf(), local_config())
+        .await
+        .unwrap();
+    assert_eq!(after.lock().await.node_id(), rotated.public());
+    after.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn direct_only_accepts_local_connections() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = BridgeConfig {
+        path_policy: PathPolicy::DirectOnly,
+        ..local_config()
+    };
+    let bridge = start_bridge(echo_server().await, dir.path().to_path_buf(), config)
+        .await
+        .unwrap();
+    let client = client_endpoint().await;
+
+    let (_connection, send, recv) = open_pipe(&client, &bridge, &HANDSHAKE).await;
+    let echoed = echo(send, recv, b"same building".to_vec()).await;
+
+    assert_eq!(echoed, b"same building");
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn prefer_direct_forwards_relayed_connections_straight_away() {
+    let (relay_map, relay_url, _relay) = iroh::test_utils::run_relay_server().await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_bridge(
+        echo_server().await,
+        dir.path().to_path_buf(),
+        relay_config(&relay_url),
+    )
+    .await
+    .unwrap();
+    let client = relay_client_endpoint(relay_map).await;
+
+    // Only the relay address, so the connection starts out relayed
+    let addr = EndpointAddr::new(bridge.lock().await.node_id()).with_relay_url(relay_url);
+    let started = std::time::Instant::now();
+    let (_connection, send, recv) = open_pipe_to(&client, addr, &HANDSHAKE).await;
+    let echoed = echo(send, recv, b"over the relay".to_vec()).await;
+
+    assert_eq!(echoed, b"over the relay");
+    assert!(
+        started.elapsed() < Duration::from_secs(3),
+        "relayed connection was held back for {:?}",
+        started.elapsed()
+    );
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn losing_the_relay_degrades_the_bridge() {
+    let (_relay_map, relay_url, relay) = iroh::test_utils::run_relay_server().await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_bridge(
+        echo_server().await,
+        dir.path().to_path_buf(),
+        relay_config(&relay_url),
+    )
+    .await
+    .unwrap();
+    let mut health = RelayHealth::default();
+
+    let connected = wait_for_relay_change(&bridge, &mut health).await;
+    assert_eq!(connected, RelayChange::Connected);
+
+    relay.shutdown().await.unwrap();
+    let lost = wait_for_relay_change(&bridge, &mut health).await;
+    assert_eq!(lost, RelayChange::Lost);
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[test]
+fn relay_health_reports_each_change_once() {
+    let mut health = RelayHealth::default();
+    assert_eq!(health.update(false), None);
+    assert_eq!(health.update(true), Some(RelayChange::Connected));
+    assert_eq!(health.update(true), None);
+    assert_eq!(health.update(false), Some(RelayChange::Lost));
+    assert_eq!(health.update(false), None);
+    assert_eq!(health.update(true), Some(RelayChange::Reconnected));
+}
+
+#[tokio::test]
+async fn direct_only_refuses_relayed_connections() {
+    let (relay_map, relay_url, _relay) = iroh::test_utils::run_relay_server().await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let config = BridgeConfig {
+        path_policy: PathPolicy::DirectOnly,
+        ..relay_config(&relay_url)
+    };
+    let bridge = start_bridge(echo_server().await, dir.path().to_path_buf(), config)
+        .await
+        .unwrap();
+    assert_eq!(bridge.lock().await.relay_url(), None);
+    let client = relay_client_endpoint(relay_map).await;
+
+    let addr = EndpointAddr::new(bridge.lock().await.node_id()).with_relay_url(relay_url);
+    let result = tokio::time::timeout(STEP_TIMEOUT, client.connect(addr, ALPN)).await;
+
+    assert!(!matches!(result, Ok(Ok(_))), "connected through the relay");
+    assert!(bridge.lock().await.connections().is_empty());
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+/// Dial over loopback only, so pairing sees a local address whatever
+/// network the test machine sits on
+fn loopback_addr(endpoint: &Endpoint) -> EndpointAddr {
+    let loopback = endpoint
+        .bound_sockets()
+        .into_iter()
+        .filter(|addr| addr.is_ipv4())
+        .map(|addr| iroh::TransportAddr::Ip((Ipv4Addr::LOCALHOST, addr.port()).into()));
+    EndpointAddr::from_parts(endpoint.id(), loopback)
+}
+
+#[tokio::test]
+async fn pairing_code_is_traded_for_the_ticket_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = BridgeConfig {
+        lan_discovery: true,
+        ..local_config()
+    };
+    let bridge = start_bridge(echo_server().await, dir.path().to_path_buf(), config)
+        .await
+        .unwrap();
+    let client = client_endpoint().await;
+
+    let (addr, code) = {
+        let bridge_locked = bridge.lock().await;
+        let code = bridge_locked.create_pairing_code().unwrap();
+        (loopback_addr(&bridge_locked.endpoint), code.code)
+    };
+    assert_eq!(code.len(), 6);
+
+    let ticket = request_ticket(&client, addr.clone(), &code).await.unwrap();
+    assert_eq!(ticket, bridge.lock().await.ticket());
+
+    let reused = request_ticket(&client, addr.clone(), &code).await;
+    assert!(reused.is_err(), "a pairing code worked twice");
+    let wrong = request_ticket(&client, addr, "not-a-code").await;
+    assert!(wrong.is_err());
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[test]
+fn wrong_pairing_codes_only_lock_out_the_guesser() {
+    let codes = PairingCodes::default();
+    let guesser = SecretKey::generate(&mut rand::rng()).public();
+    let other = SecretKey::generate(&mut rand::rng()).public();
+    let code = codes.issue().code;
+
+    for _ in 0..20 {
+        assert!(!codes.redeem(guesser, "wrong"));
+    }
+    // Even the right code is refused once locked out
+    assert!(!codes.redeem(guesser, &code));
+    assert!(codes.redeem(other, &code));
+}
+
+#[test]
+fn wrong_pairing_codes_from_many_remotes_revoke_every_code() {
+    let codes = PairingCodes::default();
+    let first = codes.issue().code;
+    let second = codes.issue().code;
+
+    // A fresh node id per guess dodges the per-remote lockout
+    let wrong = if first == "000000" || second == "000000" {
+        "999999"
+    } else {
+        "000000"
+    };
+    for _ in 0..100 {
+        let guesser = SecretKey::generate(&mut rand::rng()).public();
+        assert!(!codes.redeem(guesser, wrong));
+    }
+
+    let honest = SecretKey::generate(&mut rand::rng()).public();
+    assert!(!codes.redeem(honest, &first));
+    assert!(!codes.redeem(honest, &second));
+
+    // Codes issued afterwards work again
+    let fresh = codes.issue().code;
+    assert!(codes.redeem(honest, &fresh));
+}
+
+#[test]
+fn only_local_network_addresses_may_pair() {
+    let local = [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.9",
+        "192.168.1.20",
+        "169.254.10.1",
+        "::1",
+        "fd12:3456::1",
+        "fe80::1",
+        "::ffff:192.168.1.20",
+    ];
+    for ip in local {
+        assert!(connections::is_local_network(ip.parse().unwrap()), "{ip}");
+    }
+
+    let remote = ["8.8.8.8", "100.64.0.1", "2001:db8::1", "::ffff:1.1.1.1"];
+    for ip in remote {
+        assert!(!connections::is_local_network(ip.parse().unwrap()), "{ip}");
+    }
+}
+
+#[tokio::test]
+async fn pairing_is_off_without_lan_discovery() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    assert!(bridge.lock().await.create_pairing_code().is_none());
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+/// Time `acquire(bytes)` takes on the paused test clock
+async fn time_acquire(limiter: &rate_limit::RateLimiter, bytes: usize) -> Duration {
+    let started = tokio::time::Instant::now();
+    limiter.acquire(bytes).await;
+    started.elapsed()
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limiter_refills_at_its_rate() {
+    let limiter = rate_limit::RateLimiter::new(1000);
+
+    // A full bucket lets the first second's worth straight through
+    assert_eq!(time_acquire(&limiter, 1000).await, Duration::ZERO);
+    // Then bytes trickle in at the configured rate
+    assert_eq!(
+        time_acquire(&limiter, 500).await,
+        Duration::from_millis(500)
+    );
+    tokio::time::advance(Duration::from_millis(250)).await;
+    assert_eq!(
+        time_acquire(&limiter, 500).await,
+        Duration::from_millis(250)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limiter_bursts_at_most_one_second() {
+    let limiter = rate_limit::RateLimiter::new(1000);
+    limiter.acquire(1000).await;
+
+    // A long quiet spell doesn't bank more than a second of bytes
+    tokio::time::advance(Duration::from_secs(10)).await;
+    assert_eq!(time_acquire(&limiter, 1000).await, Duration::ZERO);
+    assert_eq!(time_acquire(&limiter, 1000).await, Duration::from_secs(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limiter_takes_turns_between_senders() {
+    let limiter = Arc::new(rate_limit::RateLimiter::new(100));
+    limiter.acquire(100).await;
+    let turns = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let sender = |name: char| {
+        let limiter = limiter.clone();
+        let turns = turns.clone();
+        tokio::spawn(async move {
+            for _ in 0..5 {
+                limiter.acquire(100).await;
+                turns.lock().unwrap().push(name);
+            }
+        })
+    };
+    let (a, b) = (sender('a'), sender('b'));
+    a.await.unwrap();
+    b.await.unwrap();
+
+    let turns: String = turns.lock().unwrap().iter().collect();
+    assert_eq!(turns, "ababababab");
+}
+
+#[tokio::test(start_paused = true)]
+async fn bandwidth_limits_apply_per_connection_and_globally() {
+    /// Send 2000 bytes to the remote on each of two connections at once
+    async fn two_uploads(limits: BandwidthLimits) -> Duration {
+        let started = tokio::time::Instant::now();
+        let upload = || {
+            let (to_remote, _) = limits.for_connection();
+            async move {
+                to_remote.acquire(1000).await;
+                to_remote.acquire(1000).await;
+            }
+        };
+        tokio::join!(upload(), upload());
+        started.elapsed()
+    }
+
+    // Each connection has its own bucket, so they run side by side
+    let per_connection = BandwidthLimits::new(&config::BandwidthConfig {
+        per_connection_bytes_per_sec: Some(1000),
+        global_bytes_per_sec: None,
+    });
+    assert_eq!(two_uploads(per_connection).await, Duration::from_secs(1));
+
+    // One shared bucket carries all 4000 bytes
+    let global = BandwidthLimits::new(&config::BandwidthConfig {
+        per_connection_bytes_per_sec: None,
+        global_bytes_per_sec: Some(1000),
+    });
+    assert_eq!(two_uploads(global).await, Duration::from_secs(3));
+
+    // Downloads have a bucket of their own
+    let limits = BandwidthLimits::new(&config::BandwidthConfig {
+        per_connection_bytes_per_sec: None,
+        global_bytes_per_sec: Some(1000),
+    });
+    let (to_remote, from_remote) = limits.for_connection();
+    let started = tokio::time::Instant::now();
+    to_remote.acquire(1000).await;
+    from_remote.acquire(1000).await;
+    assert_eq!(started.elapsed(), Duration::ZERO);
+}
+
+#[test]
+fn traffic_resets_the_idle_time() {
+    let counters = connections::ByteCounters::default();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(counters.idle_for() >= Duration::from_millis(50));
+
+    counters.add_in(1);
+    assert!(counters.idle_for() < Duration::from_millis(50));
+}
+
+#[test]
+fn renders_ticket_as_svg_qr_code() {
+    let svg = ticket_qr_svg("endpointabc123").unwrap();
+    assert!(svg.contains("<svg"));
+}
+
+#[tokio::test]
+async fn client_pipes_tcp_through_the_bridge() {
+    let dir = tempfile::tempdir().unwrap();
+    let bridge = start_local_bridge(dir.path()).await;
+    let client = super::client::client_endpoint(&local_config())
+        .await
+        .unwrap();
+    let addr = bridge.lock().await.endpoint.addr();
+
+    // Stand in for the app the CLI exposes on a local port
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut local = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (accepted, _) = listener.accept().await.unwrap();
+    tokio::spawn(async move { connect_tcp(&client, addr, accepted).await });
+
+    local.write_all(b"through the cli").await.unwrap();
+    let mut echoed = [0u8; 15];
+    tokio::time::timeout(STEP_TIMEOUT, local.read_exact(&mut echoed))
+        .await
+        .expect("echo timed out")
+        .unwrap();
+
+    assert_eq!(&echoed, b"through the cli");
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn publishes_ticket_and_node_id_to_data_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(read_node_id(dir.path()).unwrap().is_none());
+
+    let bridge = start_local_bridge(dir.path()).await;
+    let (ticket, node_id) = {
+        let bridge_locked = bridge.lock().await;
+        (bridge_locked.ticket().to_string(), bridge_locked.node_id())
+    };
+
+    assert_eq!(read_ticket(dir.path()).unwrap(), Some(ticket));
+    assert_eq!(read_node_id(dir.path()).unwrap(), Some(node_id));
+    bridge.lock().await.shutdown().await.unwrap();
+}
+
+#[test]
+fn loads_settings_saved_by_the_app() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = dir.path().join("config.json");
+    assert_eq!(
+        BridgeConfig::load_from_store(&store).unwrap(),
+        BridgeConfig::default()
+    );
+
+    std::fs::write(
+        &store,
+        r#"{"irohEnabled":false,"iroh":{"lan_discovery":false,"path_policy":"direct-only"}}"#,
+    )
+    .unwrap();
+    let config = BridgeConfig::load_from_store(&store).unwrap();
+    assert!(!config.lan_discovery);
+    assert_eq!(config.path_policy, PathPolicy::DirectOnly);
+    // Quiet remotes stay connected unless the user opts in to a timeout
+    assert_eq!(config.timeouts.idle_timeout(), None);
+
+    // `allow-relay` was folded into `prefer-direct`
+    std::fs::write(&store, r#"{"iroh":{"path_policy":"allow-relay"}}"#).unwrap();
+    let config = BridgeConfig::load_from_store(&store).unwrap();
+    assert_eq!(config.path_policy, PathPolicy::PreferDirect);
+}
+
+#[cfg(unix)]
+#[test]
+fn secret_key_is_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    rotate_secret(dir.path()).unwrap();
+
+    let mode = std::fs::metadata(dir.path().join("iroh_secret_key"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[cfg(unix)]
+#[test]
+fn tightens_existing_secret_key_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let key_path = dir.path().join("iroh_secret_key");
+    let original = rotate_secret(dir.path()).unwrap();
+    std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    let loaded = secret::get_or_create_secret(dir.path()).unwrap();
+    assert_eq!(loaded.public(), original.public());
+    let mode = std::fs::metadata(&key_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_secret_key_is_an_error_not_a_new_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    // A directory in the key's place can't be read as a file
+    std::fs::create_dir(dir.path().join("iroh_secret_key")).unwrap();
+
+    assert!(secret::get_or_create_secret(dir.path()).is_err());
+    assert!(dir.path().join("iroh_secret_key").is_dir());
+}
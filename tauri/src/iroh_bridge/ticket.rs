@@ -0,0 +1,24 @@
+use anyhow::{Context, Result};
+use std::{fs, io::ErrorKind, path::Path};
+
+/// File in the data dir holding the ticket the bridge last published, so
+/// tools running next to the app can hand it out without binding an endpoint
+const TICKET_FILE: &str = "iroh_ticket";
+
+/// Record the bridge's current ticket
+pub fn save_ticket(data_dir: &Path, ticket: &str) -> Result<()> {
+    fs::create_dir_all(data_dir).context("Failed to create data directory")?;
+    let tmp_path = data_dir.join(format!("{}.tmp", TICKET_FILE));
+    fs::write(&tmp_path, ticket).context("Failed to write ticket")?;
+    fs::rename(&tmp_path, data_dir.join(TICKET_FILE)).context("Failed to replace ticket")?;
+    Ok(())
+}
+
+/// The ticket the bridge last published, if it ever ran from this data dir
+pub fn read_ticket(data_dir: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(data_dir.join(TICKET_FILE)) {
+        Ok(ticket) => Ok(Some(ticket.trim().to_string())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context("Failed to read ticket"),
+    }
+}
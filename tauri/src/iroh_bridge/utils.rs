@@ -1,27 +1,73 @@
-use std::io;
-use tokio::io::{AsyncRead, AsyncWrite};
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio_util::sync::CancellationToken;
 
-/// Creates a closure that cancels the token and returns the value
-pub fn cancel_token<T>(token: CancellationToken) -> impl Fn(T) -> T {
-    move |x| {
-        token.cancel();
-        x
+use super::{connections::ByteCounters, rate_limit::DirectionLimits};
+
+/// Bytes moved per read. Small enough that rate-limited connections
+/// interleave smoothly.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Current time as a Unix timestamp in milliseconds
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Like `tokio::io::copy`, but waits on the rate limits before each chunk and
+/// reports the bytes as they go
+async fn copy_limited(
+    from: &mut (impl AsyncRead + Unpin),
+    to: &mut (impl AsyncWrite + Unpin),
+    limits: &DirectionLimits,
+    record: impl Fn(u64),
+) -> io::Result<u64> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut total = 0;
+    loop {
+        let read = from.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        limits.acquire(read).await;
+        to.write_all(&buf[..read]).await?;
+        record(read as u64);
+        total += read as u64;
     }
+    to.flush().await?;
+    Ok(total)
 }
 
-/// Copy data from an async reader to a Quinn send stream
+/// Copy data from an async reader to a Quinn send stream.
+/// EOF on the reader finishes the stream so the remote sees it too.
 pub async fn copy_to_quinn(
     mut from: impl AsyncRead + Unpin,
     mut send: quinn::SendStream,
     token: CancellationToken,
+    counters: Arc<ByteCounters>,
+    limits: DirectionLimits,
 ) -> io::Result<u64> {
     tracing::trace!("copying to quinn");
+    let copy = async {
+        let size = copy_limited(&mut from, &mut send, &limits, |n| counters.add_out(n)).await?;
+        send.finish()?;
+        // Dropping the connection discards unacknowledged data, so wait
+        // until the remote has everything
+        send.stopped().await.map_err(io::Error::other)?;
+        Ok(size)
+    };
     tokio::select! {
-        res = tokio::io::copy(&mut from, &mut send) => {
-            let size = res?;
-            send.finish()?;
-            Ok(size)
+        res = copy => {
+            if res.is_err() {
+                send.reset(0u8.into()).ok();
+            }
+            res
         }
         _ = token.cancelled() => {
             // send a reset to the other side immediately
@@ -31,15 +77,27 @@ pub async fn copy_to_quinn(
     }
 }
 
-/// Copy data from a Quinn receive stream to an async writer
+/// Copy data from a Quinn receive stream to an async writer.
+/// When the remote finishes its stream the writer is shut down in turn.
 pub async fn copy_from_quinn(
     mut recv: quinn::RecvStream,
     mut to: impl AsyncWrite + Unpin,
     token: CancellationToken,
+    counters: Arc<ByteCounters>,
+    limits: DirectionLimits,
 ) -> io::Result<u64> {
     tokio::select! {
-        res = tokio::io::copy(&mut recv, &mut to) => {
-            Ok(res?)
+        res = copy_limited(&mut recv, &mut to, &limits, |n| counters.add_in(n)) => {
+            match res {
+                Ok(size) => {
+                    to.shutdown().await?;
+                    Ok(size)
+                }
+                Err(e) => {
+                    recv.stop(0u8.into()).ok();
+                    Err(e)
+                }
+            }
         },
         _ = token.cancelled() => {
             recv.stop(0u8.into()).ok();
@@ -48,32 +106,67 @@ pub async fn copy_from_quinn(
     }
 }
 
-/// Forward data bidirectionally between async streams and Quinn streams
+/// Cancel `token` once no byte has moved for `timeout`
+async fn cancel_when_idle(
+    counters: Arc<ByteCounters>,
+    timeout: Duration,
+    token: CancellationToken,
+) {
+    loop {
+        let idle = counters.idle_for();
+        if idle >= timeout {
+            tracing::info!("Closing connection idle for {:?}", idle);
+            token.cancel();
+            return;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(timeout - idle) => {}
+            _ = token.cancelled() => return,
+        }
+    }
+}
+
+/// Forward data bidirectionally between async streams and Quinn streams.
+///
+/// Each direction closes on its own: EOF is passed along and the other
+/// direction keeps going, so a half-closed connection still drains. Only the
+/// idle timeout or cancelling `token` tears both down at once.
+#[allow(clippy::too_many_arguments)]
 pub async fn forward_bidi(
     from1: impl AsyncRead + Send + Sync + Unpin + 'static,
     to1: impl AsyncWrite + Send + Sync + Unpin + 'static,
     from2: quinn::RecvStream,
     to2: quinn::SendStream,
+    counters: Arc<ByteCounters>,
+    (upload, download): (DirectionLimits, DirectionLimits),
+    idle_timeout: Option<Duration>,
+    token: CancellationToken,
 ) -> anyhow::Result<()> {
-    let token1 = CancellationToken::new();
-    let token2 = token1.clone();
-    let token3 = token1.clone();
-    let forward_from_stdin = tokio::spawn(async move {
-        copy_to_quinn(from1, to2, token1.clone())
-            .await
-            .map_err(cancel_token(token1))
-    });
-    let forward_to_stdout = tokio::spawn(async move {
-        copy_from_quinn(from2, to1, token2.clone())
-            .await
-            .map_err(cancel_token(token2))
-    });
-    let _control_c = tokio::spawn(async move {
-        tokio::signal::ctrl_c().await?;
-        token3.cancel();
-        io::Result::Ok(())
-    });
-    let _ = forward_to_stdout.await;
-    let _ = forward_from_stdin.await;
+    let forward_from_stdin = tokio::spawn(copy_to_quinn(
+        from1,
+        to2,
+        token.clone(),
+        counters.clone(),
+        upload,
+    ));
+    let forward_to_stdout = tokio::spawn(copy_from_quinn(
+        from2,
+        to1,
+        token.clone(),
+        counters.clone(),
+        download,
+    ));
+    let idle = idle_timeout
+        .map(|timeout| tokio::spawn(cancel_when_idle(counters, timeout, token.clone())));
+
+    if let Ok(Err(e)) = forward_to_stdout.await {
+        tracing::debug!("Forwarding to local server ended: {}", e);
+    }
+    if let Ok(Err(e)) = forward_from_stdin.await {
+        tracing::debug!("Forwarding to remote ended: {}", e);
+    }
+    if let Some(idle) = idle {
+        idle.abort();
+    }
     Ok(())
 }
@@ -1,43 +1,185 @@
 use std::net::SocketAddrV4;
+use std::str::FromStr;
 use std::sync::Arc;
 
-use tauri::{Manager, State};
-use tokio::sync::Mutex as TokioMutex;
+use iroh_base::PublicKey;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::{broadcast::error::RecvError, Mutex as TokioMutex};
 
-use crate::iroh_bridge;
+use crate::{iroh_bridge, iroh_registration, iroh_supervisor, settings};
 
-pub type IrohBridgeState = Arc<TokioMutex<Option<Arc<TokioMutex<iroh_bridge::IrohBridge>>>>>;
+pub type IrohBridgeHandle = Arc<TokioMutex<iroh_bridge::IrohBridge>>;
+pub type IrohBridgeState = Arc<TokioMutex<BridgeState>>;
+
+/// Lifecycle of the bridge, shared by the auto-start and the commands
+#[derive(Default)]
+pub enum BridgeState {
+    #[default]
+    Stopped,
+    Starting,
+    Running(IrohBridgeHandle),
+    Failed {
+        reason: String,
+        /// Unix timestamp in milliseconds of the first failure in a row
+        since: u64,
+    },
+}
+
+impl BridgeState {
+    /// The running bridge, if there is one
+    pub fn bridge(&self) -> Option<&IrohBridgeHandle> {
+        match self {
+            BridgeState::Running(bridge) => Some(bridge),
+            _ => None,
+        }
+    }
+
+    /// Take the running bridge out, leaving the state `Stopped`
+    pub fn take_bridge(&mut self) -> Option<IrohBridgeHandle> {
+        match std::mem::take(self) {
+            BridgeState::Running(bridge) => Some(bridge),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            BridgeState::Stopped => "stopped",
+            BridgeState::Starting => "starting",
+            BridgeState::Running(_) => "running",
+            BridgeState::Failed { .. } => "failed",
+        }
+    }
+}
 
 /// Response type for iroh bridge status
-#[derive(serde::Serialize)]
+#[derive(Clone, serde::Serialize)]
 pub struct IrohBridgeStatus {
+    /// One of `stopped`, `starting`, `running` or `failed`
+    pub state: String,
     pub enabled: bool,
     pub ticket: Option<String>,
     pub node_id: Option<String>,
+    pub relay_url: Option<String>,
+    pub direct_addresses: Vec<String>,
+    pub uptime_secs: Option<u64>,
+    pub path_policy: iroh_bridge::PathPolicy,
+    /// Open connections, including the path each one takes
+    pub connections: Vec<iroh_bridge::ConnectionInfo>,
+    pub last_error: Option<String>,
+    /// Unix timestamp in milliseconds, set while the state is `failed`
+    pub failed_since: Option<u64>,
+    /// Last connection info the node server accepted. Its ticket differs
+    /// from `ticket` while a re-registration is still pending.
+    pub last_registration: Option<iroh_registration::HostRegistration>,
+}
+
+impl IrohBridgeStatus {
+    pub(crate) fn running(app: &AppHandle, bridge: &iroh_bridge::IrohBridge) -> Self {
+        Self {
+            state: "running".to_string(),
+            enabled: true,
+            ticket: Some(bridge.ticket().to_string()),
+            node_id: Some(bridge.node_id().to_string()),
+            relay_url: bridge.relay_url(),
+            direct_addresses: bridge.direct_addresses(),
+            uptime_secs: Some(bridge.uptime().as_secs()),
+            path_policy: bridge.config().path_policy,
+            connections: bridge.connections(),
+            last_error: iroh_supervisor::last_error(app),
+            failed_since: None,
+            last_registration: iroh_registration::last_success(app),
+        }
+    }
+
+    pub(crate) async fn of(app: &AppHandle, state: &BridgeState) -> Self {
+        if let BridgeState::Running(bridge) = state {
+            return Self::running(app, &*bridge.lock().await);
+        }
+
+        let (last_error, failed_since) = match state {
+            BridgeState::Failed { reason, since } => (Some(reason.clone()), Some(*since)),
+            _ => (iroh_supervisor::last_error(app), None),
+        };
+        let config: iroh_bridge::BridgeConfig = settings::load(app, iroh_supervisor::SETTINGS_KEY);
+        Self {
+            state: state.name().to_string(),
+            enabled: false,
+            ticket: None,
+            node_id: None,
+            relay_url: None,
+            direct_addresses: vec![],
+            uptime_secs: None,
+            path_policy: config.path_policy,
+            connections: vec![],
+            last_error,
+            failed_since,
+            last_registration: iroh_registration::last_success(app),
+        }
+    }
+}
+
+/// Emitted as `iroh-bridge-state` so the UI doesn't have to poll
+#[derive(Clone, serde::Serialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum IrohBridgeEvent {
+    Starting {
+        attempt: u32,
+    },
+    /// Sent again whenever the ticket or addresses change
+    Online {
+        status: IrohBridgeStatus,
+    },
+    /// The endpoint reached its home relay, first or again after an outage
+    RelayConnected {
+        relay_url: Option<String>,
+    },
+    /// The node server accepted the current connection info
+    Registered {
+        registration: iroh_registration::HostRegistration,
+    },
+    /// Still running, but remotes may not be able to reach it
+    Degraded {
+        reason: String,
+    },
+    Stopped,
+    Error {
+        message: String,
+        retry_in_ms: Option<u64>,
+    },
+}
+
+pub(crate) fn emit_bridge_event(app: &AppHandle, event: IrohBridgeEvent) {
+    let _ = app.emit("iroh-bridge-state", event);
+}
+
+/// Relay the bridge's connection events to the frontend as `iroh-connection`
+pub(crate) fn forward_connection_events(app: AppHandle, bridge: &iroh_bridge::IrohBridge) {
+    let mut rx = bridge.subscribe_connections();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let _ = app.emit("iroh-connection", event);
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("Dropped {} iroh connection events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
 }
 
 /// Get the current status of the iroh bridge
 #[tauri::command]
 pub async fn get_iroh_status(
+    app: AppHandle,
     bridge_state: State<'_, IrohBridgeState>,
 ) -> Result<IrohBridgeStatus, String> {
     let state = bridge_state.lock().await;
-    
-    match state.as_ref() {
-        Some(bridge) => {
-            let bridge_locked = bridge.lock().await;
-            Ok(IrohBridgeStatus {
-                enabled: true,
-                ticket: Some(bridge_locked.ticket().to_string()),
-                node_id: Some(bridge_locked.node_id().to_string()),
-            })
-        }
-        None => Ok(IrohBridgeStatus {
-            enabled: false,
-            ticket: None,
-            node_id: None,
-        }),
-    }
+
+    Ok(IrohBridgeStatus::of(&app, &state).await)
 }
 
 /// Start the iroh bridge manually (if not auto-started)
@@ -46,13 +188,6 @@ pub async fn start_iroh_bridge(
     app: tauri::AppHandle,
     bridge_state: State<'_, IrohBridgeState>,
 ) -> Result<IrohBridgeStatus, String> {
-    {
-        let state = bridge_state.lock().await;
-        if state.is_some() {
-            return Err("Iroh bridge is already running".to_string());
-        }
-    }
-
     let data_dir = app
         .path()
         .app_data_dir()
@@ -62,43 +197,59 @@ pub async fn start_iroh_bridge(
         .parse()
         .map_err(|e: std::net::AddrParseError| e.to_string())?;
     
-    let bridge = iroh_bridge::start_bridge(target_addr, data_dir)
-        .await
-        .map_err(|e| e.to_string())?;
+    {
+        let mut state = bridge_state.lock().await;
+        if iroh_supervisor::is_running(&app)
+            || matches!(*state, BridgeState::Starting | BridgeState::Running(_))
+        {
+            return Err("Iroh bridge is already running".to_string());
+        }
+        *state = BridgeState::Starting;
+    }
+    emit_bridge_event(&app, IrohBridgeEvent::Starting { attempt: 1 });
+
+    let config: iroh_bridge::BridgeConfig = settings::load(&app, iroh_supervisor::SETTINGS_KEY);
+    let bridge = match iroh_bridge::start_bridge(target_addr, data_dir.clone(), config).await {
+        Ok(bridge) => bridge,
+        Err(e) => {
+            let message = e.to_string();
+            iroh_supervisor::record_error(&app, &message);
+            *bridge_state.lock().await = BridgeState::Failed {
+                reason: message.clone(),
+                since: iroh_bridge::unix_millis(),
+            };
+            emit_bridge_event(
+                &app,
+                IrohBridgeEvent::Error {
+                    message: message.clone(),
+                    retry_in_ms: None,
+                },
+            );
+            return Err(message);
+        }
+    };
     
     let status = {
         let bridge_locked = bridge.lock().await;
-        IrohBridgeStatus {
-            enabled: true,
-            ticket: Some(bridge_locked.ticket().to_string()),
-            node_id: Some(bridge_locked.node_id().to_string()),
-        }
+        IrohBridgeStatus::running(&app, &bridge_locked)
     };
     
-    {
-        let mut state = bridge_state.lock().await;
-        *state = Some(bridge);
+    // Turning it on by hand also means starting it on the next launch
+    if let Err(e) = iroh_supervisor::set_enabled(&app, true) {
+        log::error!("Failed to save iroh bridge preference: {}", e);
     }
+
+    // The supervisor takes it from here and keeps it alive
+    iroh_supervisor::spawn(app, data_dir, Some(bridge));
     
     Ok(status)
 }
 
-/// Stop the iroh bridge
+/// Stop the iroh bridge and keep it off on the next launch
 #[tauri::command]
-pub async fn stop_iroh_bridge(
-    bridge_state: State<'_, IrohBridgeState>,
-) -> Result<(), String> {
-    let bridge = {
-        let mut state = bridge_state.lock().await;
-        state.take()
-    };
-    
-    if let Some(bridge) = bridge {
-        let mut bridge_locked = bridge.lock().await;
-        bridge_locked.shutdown().await.map_err(|e| e.to_string())?;
-    }
-    
-    Ok(())
+pub async fn stop_iroh_bridge(app: tauri::AppHandle) -> Result<(), String> {
+    iroh_supervisor::set_enabled(&app, false)?;
+    iroh_supervisor::stop(&app).await.map_err(|e| e.to_string())
 }
 
 /// Get just the connection ticket string
@@ -108,7 +259,7 @@ pub async fn get_iroh_ticket(
 ) -> Result<Option<String>, String> {
     let state = bridge_state.lock().await;
     
-    match state.as_ref() {
+    match state.bridge() {
         Some(bridge) => {
             let bridge_locked = bridge.lock().await;
             Ok(Some(bridge_locked.ticket().to_string()))
@@ -116,3 +267,160 @@ pub async fn get_iroh_ticket(
         None => Ok(None),
     }
 }
+
+/// Render the connection ticket as an SVG QR code
+#[tauri::command]
+pub async fn get_iroh_ticket_qr(
+    bridge_state: State<'_, IrohBridgeState>,
+) -> Result<Option<String>, String> {
+    let state = bridge_state.lock().await;
+
+    match state.bridge() {
+        Some(bridge) => {
+            let bridge_locked = bridge.lock().await;
+            iroh_bridge::ticket_qr_svg(bridge_locked.ticket())
+                .map(Some)
+                .map_err(|e| e.to_string())
+        }
+        None => Ok(None),
+    }
+}
+
+/// Create a short-lived code a device on the LAN can trade for the ticket
+#[tauri::command]
+pub async fn create_iroh_pairing_code(
+    bridge_state: State<'_, IrohBridgeState>,
+) -> Result<iroh_bridge::PairingCode, String> {
+    let state = bridge_state.lock().await;
+    let bridge = state
+        .bridge()
+        .ok_or_else(|| "Iroh bridge is not running".to_string())?;
+
+    bridge
+        .lock()
+        .await
+        .create_pairing_code()
+        .ok_or_else(|| "Pairing needs LAN discovery to be enabled".to_string())
+}
+
+/// Trade a pairing code for the ticket of a Studio found on the LAN
+#[tauri::command]
+pub async fn redeem_iroh_pairing_code(
+    bridge_state: State<'_, IrohBridgeState>,
+    node_id: String,
+    code: String,
+) -> Result<String, String> {
+    let remote_id = PublicKey::from_str(&node_id).map_err(|e| e.to_string())?;
+    // Don't hold the bridge lock while talking to the other Studio
+    let endpoint = {
+        let state = bridge_state.lock().await;
+        let bridge = state
+            .bridge()
+            .ok_or_else(|| "Iroh bridge is not running".to_string())?;
+        let endpoint = bridge.lock().await.endpoint();
+        endpoint
+    };
+
+    iroh_bridge::request_ticket(&endpoint, remote_id, &code)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List the connections currently forwarded through the bridge
+#[tauri::command]
+pub async fn get_iroh_connections(
+    bridge_state: State<'_, IrohBridgeState>,
+) -> Result<Vec<iroh_bridge::ConnectionInfo>, String> {
+    let state = bridge_state.lock().await;
+
+    match state.bridge() {
+        Some(bridge) => Ok(bridge.lock().await.connections()),
+        None => Ok(vec![]),
+    }
+}
+
+/// Bytes forwarded through the bridge since it started
+#[tauri::command]
+pub async fn get_iroh_traffic(
+    bridge_state: State<'_, IrohBridgeState>,
+) -> Result<Option<iroh_bridge::TrafficStats>, String> {
+    let state = bridge_state.lock().await;
+
+    match state.bridge() {
+        Some(bridge) => Ok(Some(bridge.lock().await.traffic())),
+        None => Ok(None),
+    }
+}
+
+/// Forcibly close every connection from a peer. Returns how many were closed.
+#[tauri::command]
+pub async fn disconnect_iroh_peer(
+    bridge_state: State<'_, IrohBridgeState>,
+    remote_id: String,
+) -> Result<usize, String> {
+    let remote_id = PublicKey::from_str(&remote_id).map_err(|e| e.to_string())?;
+    let state = bridge_state.lock().await;
+
+    match state.bridge() {
+        Some(bridge) => Ok(bridge.lock().await.disconnect(remote_id)),
+        None => Err("Iroh bridge is not running".to_string()),
+    }
+}
+
+/// Get the persisted bridge settings
+#[tauri::command]
+pub fn get_iroh_settings(app: AppHandle) -> iroh_bridge::BridgeConfig {
+    settings::load(&app, iroh_supervisor::SETTINGS_KEY)
+}
+
+/// Persist new bridge settings and restart the bridge so they take effect
+#[tauri::command]
+pub async fn set_iroh_settings(
+    app: AppHandle,
+    config: iroh_bridge::BridgeConfig,
+) -> Result<(), String> {
+    config.validate().map_err(|e| e.to_string())?;
+    settings::save(&app, iroh_supervisor::SETTINGS_KEY, &config)?;
+    iroh_supervisor::restart(&app)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List the Studios advertising themselves on the local network
+#[tauri::command]
+pub async fn discover_studios(
+    bridge_state: State<'_, IrohBridgeState>,
+) -> Result<Vec<iroh_bridge::DiscoveredStudio>, String> {
+    let state = bridge_state.lock().await;
+    let bridge = state
+        .bridge()
+        .ok_or_else(|| "Iroh bridge is not running".to_string())?;
+
+    bridge
+        .lock()
+        .await
+        .discovered_studios()
+        .ok_or_else(|| "LAN discovery is disabled".to_string())
+}
+
+/// Replace the bridge's secret key with a new one and restart the bridge
+/// under the new identity. Returns the new node id.
+#[tauri::command]
+pub async fn rotate_iroh_identity(app: AppHandle) -> Result<String, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+
+    // Close the old endpoint before its key goes away
+    let was_running = iroh_supervisor::is_running(&app);
+    if was_running {
+        iroh_supervisor::stop(&app).await.map_err(|e| e.to_string())?;
+    }
+
+    let secret_key = iroh_bridge::rotate_secret(&data_dir).map_err(|e| e.to_string())?;
+
+    // Starting again re-registers the new ticket with the server
+    if was_running {
+        iroh_supervisor::spawn(app, data_dir, None);
+    }
+
+    Ok(secret_key.public().to_string())
+}
@@ -0,0 +1,300 @@
+use std::{sync::Mutex, time::Duration};
+
+use tauri::{AppHandle, Manager};
+use tokio::{task::JoinHandle, time::sleep};
+
+use crate::{
+    iroh_bridge::unix_millis,
+    iroh_commands::{emit_bridge_event, IrohBridgeEvent},
+    SERVER_HOST,
+};
+
+/// First delay between failed registrations; doubles up to `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How often we check whether the node server restarted
+const SERVER_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a single request to the node server may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The last time the node server accepted our connection info
+#[derive(Clone, serde::Serialize)]
+pub struct HostRegistration {
+    /// Unix timestamp in milliseconds
+    pub registered_at: u64,
+    pub node_id: String,
+    pub ticket: String,
+}
+
+/// The running registration task, if any
+#[derive(Default)]
+pub struct RegistrationState {
+    task: Mutex<Option<JoinHandle<()>>>,
+    last_success: Mutex<Option<HostRegistration>>,
+}
+
+/// Keep the node server registered with this node id and ticket, replacing
+/// whatever was registered before. Retries until the server accepts it and
+/// registers again whenever the server restarts.
+///
+/// Must be called from within the async runtime.
+pub(crate) fn register(app: &AppHandle, node_id: String, ticket: String) {
+    let app_for_task = app.clone();
+    let task = tokio::spawn(async move {
+        keep_registered(app_for_task, node_id, ticket).await;
+    });
+
+    let state = app.state::<RegistrationState>();
+    let mut guard = state.task.lock().unwrap();
+    if let Some(previous) = guard.replace(task) {
+        previous.abort();
+    }
+}
+
+/// Stop retrying, e.g. because the bridge was stopped
+pub(crate) fn cancel(app: &AppHandle) {
+    let state = app.state::<RegistrationState>();
+    let mut guard = state.task.lock().unwrap();
+    if let Some(task) = guard.take() {
+        task.abort();
+    }
+}
+
+/// The most recent registration the server accepted, if any
+pub(crate) fn last_success(app: &AppHandle) -> Option<HostRegistration> {
+    app.state::<RegistrationState>()
+        .last_success
+        .lock()
+        .unwrap()
+        .clone()
+}
+
+/// What the node server reports about the process currently running
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerBoot {
+    /// Changes every time the server starts
+    boot_id: String,
+}
+
+async fn keep_registered(app: AppHandle, node_id: String, ticket: String) {
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Failed to create HTTP client for registration: {}", e);
+            return;
+        }
+    };
+    loop {
+        let boot_id = register_with_backoff(&app, &client, &node_id, &ticket).await;
+        wait_for_server_restart(&client, &boot_id).await;
+        log::info!("Node server restarted, registering the iroh bridge again");
+    }
+}
+
+async fn register_with_backoff(
+    app: &AppHandle,
+    client: &reqwest::Client,
+    node_id: &str,
+    ticket: &str,
+) -> String {
+    let mut backoff = Backoff::default();
+    loop {
+        match post_init(client, node_id, ticket).await {
+            Ok(boot_id) => {
+                log::info!("Successfully initialized host device with iroh connection info");
+                let registration = HostRegistration {
+                    registered_at: unix_millis(),
+                    node_id: node_id.to_string(),
+                    ticket: ticket.to_string(),
+                };
+                let state = app.state::<RegistrationState>();
+                *state.last_success.lock().unwrap() = Some(registration.clone());
+                emit_bridge_event(app, IrohBridgeEvent::Registered { registration });
+                return boot_id;
+            }
+            Err(e) => {
+                let delay = backoff.next_delay();
+                log::error!(
+                    "Failed to initialize host device: {}, retrying in {:?}",
+                    e,
+                    delay
+                );
+                sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Send the iroh connection info to `/device/host/init`. Returns the boot id
+/// of the server that accepted it.
+async fn post_init(
+    client: &reqwest::Client,
+    node_id: &str,
+    ticket: &str,
+) -> Result<String, String> {
+    let init_url = format!("{}/device/host/init", SERVER_HOST);
+    let init_body = serde_json::json!({
+        "irohEndpointId": node_id,
+        "irohTicket": ticket
+    });
+
+    let response = client
+        .post(&init_url)
+        .header("x-top-csrf-protection", "1")
+        .json(&init_body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let boot: ServerBoot = response.json().await.map_err(|e| e.to_string())?;
+    Ok(boot.boot_id)
+}
+
+/// Ask `/device/host/status` which server process is running
+async fn get_boot_id(client: &reqwest::Client) -> Result<String, String> {
+    let status_url = format!("{}/device/host/status", SERVER_HOST);
+    let response = client
+        .get(&status_url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let boot: ServerBoot = response.json().await.map_err(|e| e.to_string())?;
+    Ok(boot.boot_id)
+}
+
+/// Returns once a different server process answers than the one that
+/// accepted our connection info
+async fn wait_for_server_restart(client: &reqwest::Client, boot_id: &str) {
+    let mut watch = RestartWatch::new(boot_id);
+    loop {
+        sleep(SERVER_CHECK_INTERVAL).await;
+        match watch.check(get_boot_id(client).await) {
+            ServerCheck::Restarted => return,
+            ServerCheck::Unreachable { error, first: true } => {
+                log::warn!("Node server is unreachable: {}", error);
+            }
+            ServerCheck::Running | ServerCheck::Unreachable { .. } => {}
+        }
+    }
+}
+
+/// Delays between failed registrations
+struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            next: INITIAL_BACKOFF,
+        }
+    }
+}
+
+impl Backoff {
+    /// How long to wait before the next attempt
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (delay * 2).min(MAX_BACKOFF);
+        delay
+    }
+}
+
+/// What a status check says about the server that accepted our connection info
+#[derive(Debug, PartialEq)]
+enum ServerCheck {
+    /// Same process; it still knows us
+    Running,
+    /// A different process answered. It has forgotten us, no matter how
+    /// quickly it came back.
+    Restarted,
+    /// No answer. `first` is set when it was reachable at the last check.
+    Unreachable { error: String, first: bool },
+}
+
+/// Compares status checks against the boot id we registered with
+struct RestartWatch {
+    boot_id: String,
+    reachable: bool,
+}
+
+impl RestartWatch {
+    fn new(boot_id: &str) -> Self {
+        Self {
+            boot_id: boot_id.to_string(),
+            reachable: true,
+        }
+    }
+
+    fn check(&mut self, status: Result<String, String>) -> ServerCheck {
+        match status {
+            Ok(current) if current != self.boot_id => ServerCheck::Restarted,
+            Ok(_) => {
+                self.reachable = true;
+                ServerCheck::Running
+            }
+            Err(error) => {
+                let first = std::mem::replace(&mut self.reachable, false);
+                ServerCheck::Unreachable { error, first }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::default();
+        let delays: Vec<u64> = (0..9).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60, 60]);
+    }
+
+    #[test]
+    fn a_new_boot_id_means_registering_again() {
+        let mut watch = RestartWatch::new("boot-1");
+        assert_eq!(watch.check(Ok("boot-1".into())), ServerCheck::Running);
+        assert_eq!(watch.check(Ok("boot-2".into())), ServerCheck::Restarted);
+    }
+
+    #[test]
+    fn an_outage_is_reported_once_and_only_a_restart_ends_the_wait() {
+        let mut watch = RestartWatch::new("boot-1");
+        let down = || Err("connection refused".to_string());
+
+        let first = watch.check(down());
+        assert!(matches!(
+            first,
+            ServerCheck::Unreachable { first: true, .. }
+        ));
+        let again = watch.check(down());
+        assert!(matches!(
+            again,
+            ServerCheck::Unreachable { first: false, .. }
+        ));
+
+        // Back with the same process: nothing to redo, and the next outage
+        // is worth a warning again
+        assert_eq!(watch.check(Ok("boot-1".into())), ServerCheck::Running);
+        let later = watch.check(down());
+        assert!(matches!(
+            later,
+            ServerCheck::Unreachable { first: true, .. }
+        ));
+
+        // Back as a new process, even right after an outage
+        assert_eq!(watch.check(Ok("boot-2".into())), ServerCheck::Restarted);
+    }
+}
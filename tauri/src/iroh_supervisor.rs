@@ -0,0 +1,308 @@
+use std::{
+    net::SocketAddrV4,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use tauri::{AppHandle, Manager};
+use tokio::{task::JoinHandle, time::sleep};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    iroh_bridge::{self, RelayChange, RelayHealth},
+    iroh_commands::{self, emit_bridge_event, IrohBridgeEvent, IrohBridgeStatus},
+    iroh_registration, settings, BridgeState, IrohBridgeHandle as Bridge, IrohBridgeState,
+    IROH_TARGET_ADDR,
+};
+
+/// Settings key holding the `BridgeConfig`
+pub(crate) const SETTINGS_KEY: &str = iroh_bridge::SETTINGS_KEY;
+
+/// Settings key holding whether the bridge starts with the app
+const ENABLED_KEY: &str = "irohEnabled";
+
+/// First delay between failed bind attempts; doubles up to `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How often we look at the endpoint's address and relay connection
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The running supervisor task, if any
+#[derive(Default)]
+pub struct SupervisorState {
+    supervisor: Mutex<Option<Supervisor>>,
+    pub last_error: Mutex<Option<String>>,
+}
+
+/// A supervisor task and the token that stops it
+struct Supervisor {
+    task: JoinHandle<()>,
+    cancel: CancellationToken,
+}
+
+/// Whether the bridge should start with the app. On unless the user stopped it.
+pub(crate) fn is_enabled(app: &AppHandle) -> bool {
+    settings::load::<Option<bool>>(app, ENABLED_KEY).unwrap_or(true)
+}
+
+pub(crate) fn set_enabled(app: &AppHandle, enabled: bool) -> Result<(), String> {
+    settings::save(app, ENABLED_KEY, &enabled)
+}
+
+async fn set_state(app: &AppHandle, state: BridgeState) {
+    *app.state::<IrohBridgeState>().lock().await = state;
+}
+
+/// Record a failure so it shows up as `last_error` in the bridge status
+pub(crate) fn record_error(app: &AppHandle, message: &str) {
+    let state = app.state::<SupervisorState>();
+    *state.last_error.lock().unwrap() = Some(message.to_string());
+}
+
+fn clear_error(app: &AppHandle) {
+    let state = app.state::<SupervisorState>();
+    *state.last_error.lock().unwrap() = None;
+}
+
+/// The most recent bridge failure, if any
+pub(crate) fn last_error(app: &AppHandle) -> Option<String> {
+    app.state::<SupervisorState>()
+        .last_error
+        .lock()
+        .unwrap()
+        .clone()
+}
+
+/// Whether a supervisor is currently keeping the bridge alive
+pub(crate) fn is_running(app: &AppHandle) -> bool {
+    let state = app.state::<SupervisorState>();
+    let guard = state.supervisor.lock().unwrap();
+    guard
+        .as_ref()
+        .map(|supervisor| !supervisor.task.is_finished())
+        .unwrap_or(false)
+}
+
+/// Start supervising the bridge. If `initial` is given it is adopted as the
+/// running bridge, otherwise the supervisor binds one itself.
+///
+/// Must be called from within the async runtime.
+pub(crate) fn spawn(app: AppHandle, data_dir: PathBuf, initial: Option<Bridge>) {
+    let app_for_task = app.clone();
+    let cancel = CancellationToken::new();
+    let cancel_for_task = cancel.clone();
+    let task = tokio::spawn(async move {
+        supervise(app_for_task, data_dir, initial, cancel_for_task).await;
+    });
+
+    let state = app.state::<SupervisorState>();
+    let mut guard = state.supervisor.lock().unwrap();
+    if let Some(previous) = guard.replace(Supervisor { task, cancel }) {
+        previous.cancel.cancel();
+    }
+}
+
+/// Rebuild the bridge from scratch, e.g. after its settings changed.
+/// Does nothing if the bridge is not running.
+pub(crate) async fn restart(app: &AppHandle) -> anyhow::Result<()> {
+    if !is_running(app) {
+        return Ok(());
+    }
+    let data_dir = app.path().app_data_dir()?;
+    stop(app).await?;
+    spawn(app.clone(), data_dir, None);
+    Ok(())
+}
+
+/// Stop the supervisor, then shut the bridge down
+pub(crate) async fn stop(app: &AppHandle) -> anyhow::Result<()> {
+    let supervisor = app
+        .state::<SupervisorState>()
+        .supervisor
+        .lock()
+        .unwrap()
+        .take();
+    if let Some(supervisor) = supervisor {
+        supervisor.cancel.cancel();
+        // Let it finish, so a bridge it was binding is either installed by
+        // now or already shut down
+        if let Err(e) = supervisor.task.await {
+            log::error!("Iroh bridge supervisor failed: {}", e);
+        }
+    }
+    iroh_registration::cancel(app);
+
+    let bridge = app.state::<IrohBridgeState>().lock().await.take_bridge();
+    if let Some(bridge) = bridge {
+        // Drain without the lock, so status commands aren't stuck behind it
+        let shutdown = bridge.lock().await.begin_shutdown();
+        shutdown.drain().await?;
+    }
+    emit_bridge_event(app, IrohBridgeEvent::Stopped);
+    Ok(())
+}
+
+async fn supervise(
+    app: AppHandle,
+    data_dir: PathBuf,
+    initial: Option<Bridge>,
+    cancel: CancellationToken,
+) {
+    let bridge = match initial {
+        Some(bridge) => bridge,
+        None => match bind_with_backoff(&app, &data_dir, &cancel).await {
+            Some(bridge) => bridge,
+            None => return,
+        },
+    };
+    if cancel.is_cancelled() {
+        // Stopped while binding, so nothing else knows about this bridge
+        if let Err(e) = bridge.lock().await.shutdown().await {
+            log::error!("Failed to shut down iroh bridge: {}", e);
+        }
+        return;
+    }
+
+    install(&app, &bridge).await;
+    tokio::select! {
+        _ = watch(&app, &bridge) => {}
+        _ = cancel.cancelled() => {}
+    }
+}
+
+/// Keep trying to bind the endpoint, backing off between failures. `None`
+/// once `cancel` fires.
+async fn bind_with_backoff(
+    app: &AppHandle,
+    data_dir: &Path,
+    cancel: &CancellationToken,
+) -> Option<Bridge> {
+    let target_addr: SocketAddrV4 = IROH_TARGET_ADDR.parse().expect("Invalid server address");
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt: u32 = 0;
+    let mut failing_since = None;
+    loop {
+        attempt += 1;
+        set_state(app, BridgeState::Starting).await;
+        emit_bridge_event(app, IrohBridgeEvent::Starting { attempt });
+
+        // Re-read every attempt so a fixed setting takes effect on the next try
+        let config: iroh_bridge::BridgeConfig = settings::load(app, SETTINGS_KEY);
+        match iroh_bridge::start_bridge(target_addr, data_dir.to_path_buf(), config).await {
+            Ok(bridge) => return Some(bridge),
+            Err(e) => {
+                log::error!("Failed to start iroh bridge (attempt {}): {}", attempt, e);
+                let message = e.to_string();
+                record_error(app, &message);
+                let since = *failing_since.get_or_insert_with(iroh_bridge::unix_millis);
+                set_state(
+                    app,
+                    BridgeState::Failed {
+                        reason: message.clone(),
+                        since,
+                    },
+                )
+                .await;
+                emit_bridge_event(
+                    app,
+                    IrohBridgeEvent::Error {
+                        message,
+                        retry_in_ms: Some(backoff.as_millis() as u64),
+                    },
+                );
+                tokio::select! {
+                    _ = sleep(backoff) => {}
+                    _ = cancel.cancelled() => return None,
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Make the bridge visible to the commands and tell the server about it
+async fn install(app: &AppHandle, bridge: &Bridge) {
+    // Failures from earlier attempts no longer apply
+    clear_error(app);
+    let status = {
+        let bridge_locked = bridge.lock().await;
+        iroh_commands::forward_connection_events(app.clone(), &bridge_locked);
+        IrohBridgeStatus::running(app, &bridge_locked)
+    };
+    let ticket = status.ticket.clone().unwrap_or_default();
+    let node_id = status.node_id.clone().unwrap_or_default();
+    log::info!("Iroh bridge started successfully");
+    log::info!("Connection ticket: {}", ticket);
+    log::info!("Node ID: {}", node_id);
+
+    set_state(app, BridgeState::Running(bridge.clone())).await;
+    emit_bridge_event(app, IrohBridgeEvent::Online { status });
+
+    iroh_registration::register(app, node_id, ticket);
+}
+
+/// Poll the endpoint for address changes and relay outages. iroh reconnects
+/// to the relay by itself, so an outage is only reported; direct and LAN
+/// connections keep working through it.
+async fn watch(app: &AppHandle, bridge: &Bridge) {
+    let mut relay_health = RelayHealth::default();
+    loop {
+        let (changed, status, has_relay) = {
+            let mut bridge_locked = bridge.lock().await;
+            // With relays disabled there's nothing to connect to or lose
+            let has_relay = bridge_locked
+                .config()
+                .uses_relay()
+                .then(|| bridge_locked.has_home_relay());
+            let changed = bridge_locked.refresh_ticket();
+            (
+                changed,
+                IrohBridgeStatus::running(app, &bridge_locked),
+                has_relay,
+            )
+        };
+
+        if changed {
+            let ticket = status.ticket.clone().unwrap_or_default();
+            let node_id = status.node_id.clone().unwrap_or_default();
+            log::info!("Iroh endpoint address changed, new ticket: {}", ticket);
+            emit_bridge_event(
+                app,
+                IrohBridgeEvent::Online {
+                    status: status.clone(),
+                },
+            );
+            iroh_registration::register(app, node_id, ticket);
+        }
+
+        match has_relay.and_then(|reachable| relay_health.update(reachable)) {
+            Some(change @ (RelayChange::Connected | RelayChange::Reconnected)) => {
+                if change == RelayChange::Connected {
+                    log::info!("Iroh bridge connected to its home relay");
+                } else {
+                    log::info!("Iroh bridge reconnected to its home relay");
+                }
+                emit_bridge_event(
+                    app,
+                    IrohBridgeEvent::RelayConnected {
+                        relay_url: status.relay_url.clone(),
+                    },
+                );
+            }
+            Some(RelayChange::Lost) => {
+                log::warn!("Iroh bridge lost its home relay");
+                emit_bridge_event(
+                    app,
+                    IrohBridgeEvent::Degraded {
+                        reason: "Lost connection to the home relay".to_string(),
+                    },
+                );
+            }
+            None => {}
+        }
+
+        sleep(CHECK_INTERVAL).await;
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Manager};
+
+use crate::renderer_commands;
+
+/// Stops the screensaver and display sleep while it is held
+#[derive(Default)]
+pub struct KeepAwakeState {
+    awake: Mutex<Option<keepawake::KeepAwake>>,
+}
+
+/// Keep the display awake exactly while a kiosk renderer is on screen. Call
+/// whenever renderers open, close, move or change visibility.
+pub(crate) fn refresh(app: &AppHandle) {
+    let live = renderer_commands::kiosk_live(app);
+    let state = app.state::<KeepAwakeState>();
+    let mut awake = state.awake.lock().unwrap();
+
+    if !live {
+        // Dropping the handle lets the display sleep again
+        if awake.take().is_some() {
+            log::info!("No renderer is live, allowing display sleep again");
+        }
+        return;
+    }
+    if awake.is_some() {
+        return;
+    }
+
+    let handle = keepawake::Builder::default()
+        .display(true)
+        .idle(true)
+        .reason("A renderer is presenting")
+        .app_name("TheOpenPresenter Studio")
+        .app_reverse_domain("com.theopenpresenter")
+        .create();
+    match handle {
+        Ok(handle) => {
+            log::info!("Renderer is live, keeping the display awake");
+            *awake = Some(handle);
+        }
+        Err(e) => log::error!("Failed to keep the display awake: {}", e),
+    }
+}
@@ -1,5 +1,4 @@
 use std::{
-    net::SocketAddrV4,
     process,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -13,15 +12,37 @@ use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
 use tauri_plugin_shell::{process::CommandEvent, ShellExt};
 use tokio::{time::sleep, sync::Mutex as TokioMutex};
 
-mod iroh_bridge;
+pub mod iroh_bridge;
 mod iroh_commands;
+mod iroh_registration;
+mod iroh_supervisor;
+mod keep_awake;
+mod monitors;
 mod renderer_commands;
+mod renderer_overlay;
+mod renderer_watchdog;
+mod settings;
+mod shortcuts;
 
 pub use iroh_commands::{
-    get_iroh_status, get_iroh_ticket, start_iroh_bridge, stop_iroh_bridge,
-    IrohBridgeState, IrohBridgeStatus,
+    create_iroh_pairing_code, disconnect_iroh_peer, discover_studios, get_iroh_connections,
+    get_iroh_settings, get_iroh_status, get_iroh_ticket, get_iroh_ticket_qr, get_iroh_traffic,
+    redeem_iroh_pairing_code, rotate_iroh_identity, set_iroh_settings, start_iroh_bridge,
+    stop_iroh_bridge, BridgeState, IrohBridgeEvent, IrohBridgeHandle, IrohBridgeState,
+    IrohBridgeStatus,
+};
+pub use keep_awake::KeepAwakeState;
+pub use monitors::{list_outputs, DisconnectAction, MonitorKey, OutputInfo, WorkArea};
+pub use renderer_commands::{
+    close_renderer, list_renderers, move_renderer, open_renderer, set_renderer_disconnect_action,
+    set_renderer_kiosk, RendererError, RendererInfo, RendererMode, RendererState,
+};
+pub use renderer_overlay::{set_renderer_cover, OverlayState, RendererCover};
+pub use renderer_watchdog::{renderer_heartbeat, RendererIncident, WatchdogState};
+pub use settings::STORE_FILE;
+pub use shortcuts::{
+    get_shortcuts, set_shortcut, ShortcutAction, ShortcutBinding, ShortcutBindings,
 };
-pub use renderer_commands::open_renderer;
 
 #[tauri::command]
 fn get_local_ip() -> Option<String> {
@@ -137,7 +158,7 @@ fn begin_shutdown(server: ServerHandle) {
 
 const SERVER_ORG_PAGE_URL: &str = "http://localhost:5678/o/local";
 const SERVER_HOST: &str = "http://localhost:5678";
-pub(crate) const IROH_TARGET_ADDR: &str = "127.0.0.1:5678";
+pub const IROH_TARGET_ADDR: &str = "127.0.0.1:5678";
 
 /// Always-up cloud instance that receives diagnosis bundles
 const DIAGNOSTICS_CLOUD_HOST: &str = "https://theopenpresenter.com";
@@ -311,7 +332,7 @@ async fn send_diagnosis(app_handle: AppHandle) -> Result<(), String> {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize the iroh bridge state
-    let iroh_bridge_state: IrohBridgeState = Arc::new(TokioMutex::new(None));
+    let iroh_bridge_state: IrohBridgeState = Arc::new(TokioMutex::new(BridgeState::Stopped));
 
     tauri::Builder::default()
         .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
@@ -324,19 +345,48 @@ pub fn run() {
                 let _ = window.set_focus();
             }
         }))
-        .manage(iroh_bridge_state.clone())
+        .manage(iroh_bridge_state)
+        .manage(iroh_supervisor::SupervisorState::default())
+        .manage(iroh_registration::RegistrationState::default())
+        .manage(RendererState::default())
+        .manage(OverlayState::default())
+        .manage(ShortcutBindings::default())
+        .manage(WatchdogState::default())
+        .manage(KeepAwakeState::default())
         .invoke_handler(tauri::generate_handler![
             open_renderer,
+            move_renderer,
+            close_renderer,
+            list_renderers,
+            set_renderer_disconnect_action,
+            set_renderer_kiosk,
+            list_outputs,
+            set_renderer_cover,
+            renderer_heartbeat,
+            get_shortcuts,
+            set_shortcut,
             get_iroh_status,
             start_iroh_bridge,
             stop_iroh_bridge,
             get_iroh_ticket,
+            get_iroh_ticket_qr,
+            create_iroh_pairing_code,
+            redeem_iroh_pairing_code,
+            get_iroh_connections,
+            get_iroh_traffic,
+            disconnect_iroh_peer,
+            get_iroh_settings,
+            set_iroh_settings,
+            discover_studios,
+            rotate_iroh_identity,
             get_local_ip,
             send_diagnosis
         ])
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_store::Builder::new().build())
+        .plugin(shortcuts::plugin())
         .plugin(
             tauri_plugin_log::Builder::new()
                 .rotation_strategy(tauri_plugin_log::RotationStrategy::KeepAll)
@@ -460,9 +510,15 @@ pub fn run() {
                 }
             });
 
+            // Keep renderers on their monitors as displays come and go
+            tauri::async_runtime::spawn(monitors::watch_monitors(app.handle().clone()));
+            // Reload renderers whose page crashed or hung
+            tauri::async_runtime::spawn(renderer_watchdog::watch_renderers(app.handle().clone()));
+            shortcuts::register(app.handle());
+
             // Get data dir for iroh bridge
             let data_dir = app.path().app_data_dir()?;
-            let bridge_state_for_startup = iroh_bridge_state.clone();
+            let app_for_bridge = app.handle().clone();
 
             // Wait for server and transition from splash to main window
             // Also start the iroh bridge once the server is ready
@@ -470,55 +526,15 @@ pub fn run() {
             tauri::async_runtime::spawn(async move {
                 wait_for_endpoint(SERVER_ORG_PAGE_URL).await;
                 
-                // Start the iroh bridge automatically
-                let target_addr: SocketAddrV4 = IROH_TARGET_ADDR.parse().expect("Invalid server address");
-                match iroh_bridge::start_bridge(target_addr, data_dir).await {
-                    Ok(bridge) => {
-                        let (ticket, node_id) = {
-                            let bridge_locked = bridge.lock().await;
-                            (
-                                bridge_locked.ticket().to_string(),
-                                bridge_locked.node_id().to_string()
-                            )
-                        };
-                        log::info!("Iroh bridge started successfully");
-                        log::info!("Connection ticket: {}", ticket);
-                        log::info!("Node ID: {}", node_id);
-                        
-                        let mut state = bridge_state_for_startup.lock().await;
-                        *state = Some(bridge);
-                        
-                        // Send POST request to /device/host/init
-                        let init_url = format!("{}/device/host/init", SERVER_HOST);
-                        let client = reqwest::Client::new();
-                        let init_body = serde_json::json!({
-                            "irohEndpointId": node_id,
-                            "irohTicket": ticket
-                        });
-                        
-                        match client.post(&init_url)
-                            .header("x-top-csrf-protection", "1")
-                            .json(&init_body)
-                            .send()
-                            .await
-                        {
-                            Ok(response) => {
-                                if response.status().is_success() {
-                                    log::info!("Successfully initialized host device with iroh connection info");
-                                } else {
-                                    log::error!("Failed to initialize host device: HTTP {}", response.status());
-                                }
-                            }
-                            Err(e) => {
-                                log::error!("Failed to send init request: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Failed to start iroh bridge: {}", e);
-                    }
+                // Start the iroh bridge automatically unless the user turned
+                // it off. The supervisor keeps retrying in the background, so
+                // this never holds up the UI.
+                if iroh_supervisor::is_enabled(&app_for_bridge) {
+                    iroh_supervisor::spawn(app_for_bridge, data_dir, None);
+                } else {
+                    log::info!("Iroh bridge is disabled, not starting it");
                 }
-                
+
                 main_window
                     .eval(&format!("window.location.replace('{}')", SERVER_ORG_PAGE_URL))
                     .unwrap();
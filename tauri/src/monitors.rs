@@ -0,0 +1,367 @@
+use std::{collections::HashMap, time::Duration};
+
+use tauri::{AppHandle, Emitter, Monitor};
+
+use crate::{renderer_commands, settings};
+
+/// Settings key holding which monitor each renderer output goes on
+const ASSIGNMENTS_KEY: &str = "rendererOutputs";
+
+/// How often we look for monitors being plugged in or out
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Identifies a monitor across reconnects, unlike its index in
+/// `available_monitors()`. Tauri doesn't expose serial numbers, so the
+/// geometry stands in when names are missing or change.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MonitorKey {
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl MonitorKey {
+    pub fn of(monitor: &Monitor) -> Self {
+        let size = monitor.size();
+        let position = monitor.position();
+        Self {
+            name: monitor.name().cloned(),
+            width: size.width,
+            height: size.height,
+            x: position.x,
+            y: position.y,
+        }
+    }
+
+    fn same_size(&self, other: &MonitorKey) -> bool {
+        self.width == other.width && self.height == other.height
+    }
+
+    fn same_geometry(&self, other: &MonitorKey) -> bool {
+        self.same_size(other) && self.x == other.x && self.y == other.y
+    }
+}
+
+/// What happens to a renderer while its monitor is unplugged
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisconnectAction {
+    /// Hide the renderer until its monitor is back
+    #[default]
+    Pause,
+    /// Show the renderer on the primary monitor until its monitor is back
+    Rehome,
+}
+
+/// Where an output goes, as saved in the settings
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OutputAssignment {
+    pub monitor: MonitorKey,
+    #[serde(default)]
+    pub on_disconnect: DisconnectAction,
+    /// Keep the fullscreen renderer above taskbars and notifications, out of
+    /// the taskbar, and the display awake. Ignored on the operator's monitor.
+    #[serde(default = "default_kiosk")]
+    pub kiosk: bool,
+}
+
+fn default_kiosk() -> bool {
+    true
+}
+
+/// A connected monitor as reported to the frontend
+#[derive(Clone, serde::Serialize)]
+pub struct OutputInfo {
+    /// Pass this back as `monitor` to show a renderer on this monitor
+    pub id: MonitorKey,
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+    pub scale_factor: f64,
+    /// The part of the monitor not taken by taskbars, docks or menu bars
+    pub work_area: WorkArea,
+    pub is_primary: bool,
+    /// Output ids of the renderers currently shown on this monitor
+    pub renderers: Vec<String>,
+}
+
+/// In physical pixels, like the rest of `OutputInfo`
+#[derive(Clone, serde::Serialize)]
+pub struct WorkArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Find the connected monitor best matching `key`: the same name and size,
+/// then just the same name, then the same size and position.
+pub(crate) fn find<'a>(monitors: &'a [Monitor], key: &MonitorKey) -> Option<&'a Monitor> {
+    let keys: Vec<MonitorKey> = monitors.iter().map(MonitorKey::of).collect();
+    monitors.get(best_match(&keys, key)?)
+}
+
+/// Index of the key in `keys` that `find` picks for `key`
+fn best_match(keys: &[MonitorKey], key: &MonitorKey) -> Option<usize> {
+    let same_name = |candidate: &MonitorKey| key.name.is_some() && candidate.name == key.name;
+
+    keys.iter()
+        .position(|candidate| same_name(candidate) && candidate.same_size(key))
+        .or_else(|| {
+            // A name alone is only good enough if it is unambiguous
+            let mut named = keys.iter().enumerate().filter(|(_, c)| same_name(c));
+            match (named.next(), named.next()) {
+                (Some((index, _)), None) => Some(index),
+                _ => None,
+            }
+        })
+        .or_else(|| {
+            keys.iter()
+                .position(|candidate| candidate.same_geometry(key))
+        })
+}
+
+/// Saved output id to monitor assignments
+pub(crate) fn assignments(app: &AppHandle) -> HashMap<String, OutputAssignment> {
+    settings::load(app, ASSIGNMENTS_KEY)
+}
+
+/// Remember which monitor an output goes on, keeping its other options
+pub(crate) fn save_assignment(
+    app: &AppHandle,
+    output_id: &str,
+    monitor: &MonitorKey,
+) -> Result<(), String> {
+    let mut assignments = assignments(app);
+    let assignment = reassign(assignments.get(output_id), monitor);
+    if assignments.get(output_id) == Some(&assignment) {
+        return Ok(());
+    }
+    assignments.insert(output_id.to_string(), assignment);
+    settings::save(app, ASSIGNMENTS_KEY, &assignments)
+}
+
+/// `saved` moved to `monitor`, or a new assignment with the default options
+fn reassign(saved: Option<&OutputAssignment>, monitor: &MonitorKey) -> OutputAssignment {
+    match saved {
+        Some(saved) => OutputAssignment {
+            monitor: monitor.clone(),
+            ..saved.clone()
+        },
+        None => OutputAssignment {
+            monitor: monitor.clone(),
+            on_disconnect: DisconnectAction::default(),
+            kiosk: default_kiosk(),
+        },
+    }
+}
+
+/// Change an option of an output. The output must have been shown somewhere
+/// before.
+fn update_assignment(
+    app: &AppHandle,
+    output_id: &str,
+    update: impl FnOnce(&mut OutputAssignment),
+) -> Result<(), String> {
+    let mut assignments = assignments(app);
+    let assignment = assignments
+        .get_mut(output_id)
+        .ok_or_else(|| format!("Output {} has no monitor yet", output_id))?;
+    update(assignment);
+    settings::save(app, ASSIGNMENTS_KEY, &assignments)
+}
+
+/// Change what happens to an output's renderer while its monitor is
+/// unplugged
+pub(crate) fn save_disconnect_action(
+    app: &AppHandle,
+    output_id: &str,
+    action: DisconnectAction,
+) -> Result<(), String> {
+    update_assignment(app, output_id, |assignment| {
+        assignment.on_disconnect = action
+    })
+}
+
+/// Turn the kiosk level of an output's renderer on or off
+pub(crate) fn save_kiosk(app: &AppHandle, output_id: &str, kiosk: bool) -> Result<(), String> {
+    update_assignment(app, output_id, |assignment| assignment.kiosk = kiosk)
+}
+
+/// Whether an output's renderer runs at kiosk level, on unless turned off
+pub(crate) fn kiosk(app: &AppHandle, output_id: &str) -> bool {
+    assignments(app)
+        .get(output_id)
+        .map_or_else(default_kiosk, |assignment| assignment.kiosk)
+}
+
+/// Describe every connected monitor and which renderers are on it
+pub(crate) fn outputs(app: &AppHandle) -> tauri::Result<Vec<OutputInfo>> {
+    let monitors = app.available_monitors()?;
+    let primary = app
+        .primary_monitor()?
+        .map(|monitor| MonitorKey::of(&monitor));
+    let renderers: Vec<(String, MonitorKey)> = renderer_commands::placed_renderers(app)
+        .into_iter()
+        .filter_map(|(output_id, key)| Some((output_id, MonitorKey::of(find(&monitors, &key)?))))
+        .collect();
+
+    Ok(monitors
+        .iter()
+        .map(|monitor| {
+            let id = MonitorKey::of(monitor);
+            let work_area = monitor.work_area();
+            OutputInfo {
+                name: id.name.clone(),
+                width: id.width,
+                height: id.height,
+                x: id.x,
+                y: id.y,
+                scale_factor: monitor.scale_factor(),
+                work_area: WorkArea {
+                    x: work_area.position.x,
+                    y: work_area.position.y,
+                    width: work_area.size.width,
+                    height: work_area.size.height,
+                },
+                is_primary: primary.as_ref() == Some(&id),
+                renderers: renderers
+                    .iter()
+                    .filter(|(_, key)| *key == id)
+                    .map(|(output_id, _)| output_id.clone())
+                    .collect(),
+                id,
+            }
+        })
+        .collect())
+}
+
+/// List connected monitors with their metadata and renderers
+#[tauri::command]
+pub fn list_outputs(app: AppHandle) -> Result<Vec<OutputInfo>, tauri::Error> {
+    outputs(&app)
+}
+
+fn connected(app: &AppHandle) -> Vec<MonitorKey> {
+    app.available_monitors()
+        .map(|monitors| monitors.iter().map(MonitorKey::of).collect())
+        .unwrap_or_default()
+}
+
+/// Long-running background task: poll the monitor list, emit
+/// `monitors-changed` with the new `list_outputs` when it changes, and move
+/// renderers off unplugged monitors and back onto returning ones.
+pub(crate) async fn watch_monitors(app: AppHandle) {
+    let mut last = connected(&app);
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let now = connected(&app);
+        if now == last {
+            continue;
+        }
+        let appeared: Vec<MonitorKey> = now
+            .iter()
+            .filter(|monitor| !last.contains(monitor))
+            .cloned()
+            .collect();
+        last = now;
+
+        renderer_commands::rehome_renderers(&app, &appeared);
+        match outputs(&app) {
+            Ok(outputs) => {
+                let _ = app.emit("monitors-changed", outputs);
+            }
+            Err(e) => log::error!("Failed to list monitors: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: Option<&str>, width: u32, height: u32, x: i32, y: i32) -> MonitorKey {
+        MonitorKey {
+            name: name.map(String::from),
+            width,
+            height,
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn prefers_the_same_name_and_size() {
+        let keys = [
+            key(Some("HDMI-1"), 1280, 720, 0, 0),
+            key(Some("HDMI-1"), 1920, 1080, 1920, 0),
+        ];
+        let saved = key(Some("HDMI-1"), 1920, 1080, 0, 0);
+        assert_eq!(best_match(&keys, &saved), Some(1));
+    }
+
+    #[test]
+    fn takes_a_unique_name_when_the_size_changed() {
+        let keys = [
+            key(Some("eDP-1"), 2560, 1600, 0, 0),
+            key(Some("HDMI-1"), 1280, 720, 2560, 0),
+        ];
+        let saved = key(Some("HDMI-1"), 1920, 1080, 2560, 0);
+        assert_eq!(best_match(&keys, &saved), Some(1));
+    }
+
+    #[test]
+    fn ignores_ambiguous_names() {
+        let keys = [
+            key(Some("Generic"), 1280, 720, 0, 0),
+            key(Some("Generic"), 1280, 720, 1920, 0),
+        ];
+        let saved = key(Some("Generic"), 1920, 1080, 1920, 0);
+        assert_eq!(best_match(&keys, &saved), None);
+    }
+
+    #[test]
+    fn falls_back_to_geometry_when_the_name_changed() {
+        let keys = [
+            key(Some("HDMI-1"), 1920, 1080, 0, 0),
+            key(Some("DP-2"), 1920, 1080, 1920, 0),
+        ];
+        let saved = key(Some("DP-1"), 1920, 1080, 1920, 0);
+        assert_eq!(best_match(&keys, &saved), Some(1));
+        assert_eq!(best_match(&keys, &key(None, 1920, 1080, 1920, 0)), Some(1));
+    }
+
+    #[test]
+    fn finds_nothing_without_name_or_geometry() {
+        let keys = [key(Some("HDMI-1"), 1920, 1080, 0, 0)];
+        let saved = key(Some("DP-1"), 1920, 1080, 1920, 0);
+        assert_eq!(best_match(&keys, &saved), None);
+    }
+
+    #[test]
+    fn reassigning_keeps_the_other_options() {
+        let saved = OutputAssignment {
+            monitor: key(Some("HDMI-1"), 1920, 1080, 0, 0),
+            on_disconnect: DisconnectAction::Rehome,
+            kiosk: false,
+        };
+        let moved = key(Some("DP-1"), 3840, 2160, 1920, 0);
+
+        let assignment = reassign(Some(&saved), &moved);
+        assert_eq!(assignment.monitor, moved);
+        assert_eq!(assignment.on_disconnect, DisconnectAction::Rehome);
+        assert!(!assignment.kiosk);
+    }
+
+    #[test]
+    fn new_assignments_get_the_defaults() {
+        let monitor = key(Some("HDMI-1"), 1920, 1080, 0, 0);
+        let assignment = reassign(None, &monitor);
+        assert_eq!(assignment.on_disconnect, DisconnectAction::Pause);
+        assert!(assignment.kiosk);
+    }
+}
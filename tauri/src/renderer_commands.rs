@@ -1,13 +1,201 @@
-use tauri::{Manager, Position};
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+use tauri::{
+    Manager, Monitor, PhysicalPosition, PhysicalRect, PhysicalSize, Position, Url, WebviewWindow,
+    WindowEvent,
+};
+
+use crate::{
+    keep_awake,
+    monitors::{self, DisconnectAction, MonitorKey},
+    renderer_overlay::{self, RendererCover},
+    renderer_watchdog, settings, SERVER_HOST,
+};
 
 #[cfg(target_os = "macos")]
-const ABOVE_MENU_BAR_LEVEL: isize = 25;
+pub(crate) const ABOVE_MENU_BAR_LEVEL: isize = 25;
+
+/// Window labels of renderers are this prefix plus the output id
+const RENDERER_LABEL_PREFIX: &str = "renderer-";
+
+/// Output used when the caller doesn't name one
+const DEFAULT_OUTPUT_ID: &str = "main";
+
+/// Longest output id we accept, to keep window labels sane
+const MAX_OUTPUT_ID_LEN: usize = 32;
+
+/// Share of the work area width a windowed renderer takes
+const WINDOWED_WIDTH: f64 = 0.6;
+
+/// Share of the work area width a picture-in-picture renderer takes
+const PIP_WIDTH: f64 = 0.25;
+
+/// Gap between a picture-in-picture renderer and the screen edge, in
+/// logical pixels
+const PIP_MARGIN: f64 = 24.0;
+
+/// Settings key listing extra origins renderers may load, besides the local
+/// server, e.g. `["https://theopenpresenter.com"]`
+const ALLOWED_ORIGINS_KEY: &str = "rendererAllowedOrigins";
+
+/// Why a renderer command failed. Reaches the frontend as its message.
+#[derive(Debug)]
+pub enum RendererError {
+    InvalidOutputId(String),
+    InvalidUrl(String),
+    /// The URL is fine but not on the local server or the allowlist
+    OriginNotAllowed(String),
+    MonitorNotConnected(MonitorKey),
+    NoMonitorAtIndex(usize),
+    NoMonitors,
+    NotOpen(String),
+    Tauri(tauri::Error),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOutputId(output_id) => write!(f, "Invalid output id: {:?}", output_id),
+            Self::InvalidUrl(url) => write!(f, "Invalid renderer URL: {:?}", url),
+            Self::OriginNotAllowed(origin) => {
+                write!(f, "Renderers may not load pages from {}", origin)
+            }
+            Self::MonitorNotConnected(key) => write!(f, "Monitor is not connected: {:?}", key),
+            Self::NoMonitorAtIndex(mindex) => write!(f, "There is no monitor {}", mindex),
+            Self::NoMonitors => write!(f, "No monitors are connected"),
+            Self::NotOpen(output_id) => write!(f, "Output {} has no open renderer", output_id),
+            Self::Tauri(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<tauri::Error> for RendererError {
+    fn from(e: tauri::Error) -> Self {
+        Self::Tauri(e)
+    }
+}
+
+impl serde::Serialize for RendererError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// How a renderer window is shown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RendererMode {
+    /// Covers its monitor, for the audience
+    #[default]
+    Fullscreen,
+    /// A normal window on the operator's display, for rehearsing
+    Windowed,
+    /// A small always-on-top preview in a corner of the operator's display
+    PictureInPicture,
+}
+
+/// What each open renderer is showing and where
+#[derive(Default)]
+pub struct RendererState {
+    outputs: Mutex<BTreeMap<String, RendererOutput>>,
+}
 
-/// (macOS) Lift the renderer above the Dock and menu bar.
+#[derive(Clone)]
+struct RendererOutput {
+    url: String,
+    monitor: MonitorKey,
+    mode: RendererMode,
+    /// Hidden or moved away because its monitor is unplugged
+    displaced: bool,
+    /// Raised to kiosk level right now, which `kiosk_on` decides
+    kiosk: bool,
+}
+
+/// A renderer window as reported to the frontend
+#[derive(serde::Serialize)]
+pub struct RendererInfo {
+    pub output_id: String,
+    pub url: String,
+    pub monitor: MonitorKey,
+    pub mode: RendererMode,
+    pub cover: Option<RendererCover>,
+    pub visible: bool,
+}
+
+fn renderer_label(output_id: &str) -> String {
+    format!("{}{}", RENDERER_LABEL_PREFIX, output_id)
+}
+
+/// The output id of a renderer window label, `None` for other windows
+pub(crate) fn output_id_of(label: &str) -> Option<&str> {
+    label.strip_prefix(RENDERER_LABEL_PREFIX)
+}
+
+/// The renderer window of an output, if it is open
+pub(crate) fn renderer_window(app: &tauri::AppHandle, output_id: &str) -> Option<WebviewWindow> {
+    app.get_webview_window(&renderer_label(output_id))
+}
+
+/// Output ids become part of the window label, so only allow characters
+/// Tauri accepts there
+fn validate_output_id(output_id: &str) -> Result<(), RendererError> {
+    let valid = !output_id.is_empty()
+        && output_id.len() <= MAX_OUTPUT_ID_LEN
+        && output_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(RendererError::InvalidOutputId(output_id.to_string()))
+    }
+}
+
+/// Parse a renderer URL and check it points at the local server or an
+/// origin from the allowlist in the settings
+fn validate_url(app: &tauri::AppHandle, url: &str) -> Result<Url, RendererError> {
+    let allowed_origins: Vec<String> = settings::load(app, ALLOWED_ORIGINS_KEY);
+    check_url(url, &allowed_origins)
+}
+
+/// `validate_url` without the settings: `url` must be http(s) and share its
+/// origin with the local server or one of `allowed_origins`
+fn check_url(url: &str, allowed_origins: &[String]) -> Result<Url, RendererError> {
+    let parsed = Url::parse(url).map_err(|_| RendererError::InvalidUrl(url.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(RendererError::InvalidUrl(url.to_string()));
+    }
+
+    let origin = parsed.origin();
+    let allowed = std::iter::once(SERVER_HOST)
+        .chain(allowed_origins.iter().map(String::as_str))
+        .filter_map(|allowed| match Url::parse(allowed) {
+            Ok(allowed) => Some(allowed.origin()),
+            Err(_) => {
+                log::warn!("Ignoring invalid allowed renderer origin {:?}", allowed);
+                None
+            }
+        })
+        .any(|allowed| allowed == origin);
+
+    if allowed {
+        Ok(parsed)
+    } else {
+        Err(RendererError::OriginNotAllowed(
+            origin.ascii_serialization(),
+        ))
+    }
+}
+
+/// (macOS) Set the window level, e.g. `ABOVE_MENU_BAR_LEVEL` to lift a
+/// renderer above the Dock and menu bar.
 #[cfg(target_os = "macos")]
-fn raise_above_menu_bar(
+pub(crate) fn set_window_level(
     app: &tauri::AppHandle,
     window: &tauri::WebviewWindow,
+    level: isize,
 ) -> tauri::Result<()> {
     let window = window.clone();
 
@@ -26,46 +214,124 @@ fn raise_above_menu_bar(
         // SAFETY: Tauri hands back this window's live NSWindow, and this
         // closure runs on the main thread, where AppKit requires it.
         unsafe {
-            let _: () = msg_send![ns_window, setLevel: ABOVE_MENU_BAR_LEVEL];
+            let _: () = msg_send![ns_window, setLevel: level];
         }
     })
 }
 
-/// Open or navigate the renderer window to a specified URL on a given monitor
-#[tauri::command]
-pub async fn open_renderer(
-    app: tauri::AppHandle,
-    url: String,
-    mindex: usize,
-) -> Result<(), tauri::Error> {
-    let renderer_window = match app.get_webview_window("renderer") {
-        Some(window) => window,
-        None => tauri::WebviewWindowBuilder::new(
-            &app,
-            "renderer",
-            tauri::WebviewUrl::External(url.parse().unwrap()),
-        )
-        .title("TheOpenPresenter Renderer")
-        .fullscreen(false)
-        .decorations(false)
-        .build()?,
-    };
+/// Pick the monitor for an output: an explicit key, then an index into
+/// `available_monitors()`, then the output's saved assignment, then the
+/// primary monitor.
+fn resolve_monitor(
+    app: &tauri::AppHandle,
+    output_id: &str,
+    mindex: Option<usize>,
+    monitor: Option<MonitorKey>,
+) -> Result<Monitor, RendererError> {
+    let monitors = app.available_monitors()?;
+
+    if let Some(key) = monitor {
+        return monitors::find(&monitors, &key)
+            .cloned()
+            .ok_or(RendererError::MonitorNotConnected(key));
+    }
+    if let Some(mindex) = mindex {
+        return monitors
+            .get(mindex)
+            .cloned()
+            .ok_or(RendererError::NoMonitorAtIndex(mindex));
+    }
+    let saved = monitors::assignments(app)
+        .get(output_id)
+        .and_then(|saved| monitors::find(&monitors, &saved.monitor).cloned());
+    match saved {
+        Some(monitor) => Ok(monitor),
+        None => app
+            .primary_monitor()?
+            .or_else(|| monitors.into_iter().next())
+            .ok_or(RendererError::NoMonitors),
+    }
+}
+
+/// The monitor the operator works on: wherever the main window is
+fn operator_monitor(app: &tauri::AppHandle) -> Result<Monitor, RendererError> {
+    let current = app
+        .get_webview_window("main")
+        .and_then(|window| window.current_monitor().ok().flatten());
+    match current {
+        Some(monitor) => Ok(monitor),
+        None => app.primary_monitor()?.ok_or(RendererError::NoMonitors),
+    }
+}
 
-    if let Ok(current_url) = renderer_window.url() {
-        if current_url.to_string() != url {
-            renderer_window.eval(&format!("window.location.replace('{}')", url))?;
+/// Whether an output's fullscreen renderer on `monitor` goes to kiosk level:
+/// if the output wants it and the monitor isn't the operator's, where an
+/// always-on-top renderer out of the taskbar would bury the Studio
+fn kiosk_on(app: &tauri::AppHandle, output_id: &str, monitor: &MonitorKey) -> bool {
+    let on_operator_monitor = operator_monitor(app)
+        .map(|operator| MonitorKey::of(&operator) == *monitor)
+        .unwrap_or(false);
+    monitors::kiosk(app, output_id) && !on_operator_monitor
+}
+
+/// Show the renderer window on the given monitor the way `mode` says.
+/// `kiosk` only applies to fullscreen renderers.
+fn place(
+    app: &tauri::AppHandle,
+    renderer_window: &WebviewWindow,
+    mode: RendererMode,
+    monitor: &Monitor,
+    kiosk: bool,
+) -> tauri::Result<()> {
+    match mode {
+        RendererMode::Fullscreen => place_on_monitor(app, renderer_window, monitor, kiosk),
+        RendererMode::Windowed | RendererMode::PictureInPicture => {
+            place_windowed(renderer_window, mode, monitor)
         }
     }
+}
 
-    let monitors = app.available_monitors()?;
-    let monitor = monitors.get(mindex).ok_or(tauri::Error::WindowNotFound)?;
+/// Keep a fullscreen renderer above taskbars, docks and notifications and out
+/// of the taskbar, or make it a normal window again. Some window managers
+/// ignore fullscreen, or let panels cover it, so this doesn't rely on it.
+/// On macOS a fullscreen renderer is raised above the menu bar either way.
+#[cfg_attr(not(target_os = "macos"), allow(unused_variables))]
+fn apply_kiosk(
+    app: &tauri::AppHandle,
+    renderer_window: &WebviewWindow,
+    kiosk: bool,
+) -> tauri::Result<()> {
+    if kiosk {
+        renderer_window.set_skip_taskbar(true)?;
+        renderer_window.set_always_on_top(true)?;
+    } else {
+        renderer_window.set_skip_taskbar(false)?;
+        renderer_window.set_always_on_top(false)?;
+    }
+    // macOS doesn't use native fullscreen, so only the level hides the menu
+    // bar and Dock. Set it last, as `set_always_on_top` resets it.
+    #[cfg(target_os = "macos")]
+    set_window_level(app, renderer_window, ABOVE_MENU_BAR_LEVEL)?;
+    Ok(())
+}
+
+/// Cover the given monitor with the renderer window, at kiosk level or not
+fn place_on_monitor(
+    app: &tauri::AppHandle,
+    renderer_window: &WebviewWindow,
+    monitor: &Monitor,
+    kiosk: bool,
+) -> tauri::Result<()> {
     let position = *monitor.position();
 
+    // Undo a previous windowed or picture-in-picture mode
+    renderer_window.set_decorations(false)?;
+    renderer_window.set_resizable(false)?;
+
     // macOS deliberately does NOT use native fullscreen
     #[cfg(target_os = "macos")]
     {
         let size = *monitor.size();
-        renderer_window.set_resizable(false)?;
         renderer_window.set_position(Position::Physical(tauri::PhysicalPosition {
             x: position.x,
             y: position.y,
@@ -74,7 +340,6 @@ pub async fn open_renderer(
             width: size.width,
             height: size.height,
         }))?;
-        raise_above_menu_bar(&app, &renderer_window)?;
     }
 
     #[cfg(not(target_os = "macos"))]
@@ -89,7 +354,551 @@ pub async fn open_renderer(
         renderer_window.set_fullscreen(true)?;
     }
 
+    apply_kiosk(app, renderer_window, kiosk)
+}
+
+/// Size the renderer window to part of the monitor's work area: centred
+/// when windowed, in the bottom right corner and on top of everything else
+/// as picture-in-picture
+fn place_windowed(
+    renderer_window: &WebviewWindow,
+    mode: RendererMode,
+    monitor: &Monitor,
+) -> tauri::Result<()> {
+    if renderer_window.is_fullscreen().unwrap_or(false) {
+        renderer_window.set_fullscreen(false)?;
+    }
+
+    let picture_in_picture = mode == RendererMode::PictureInPicture;
+    let rect = windowed_rect(
+        monitor.work_area(),
+        monitor.scale_factor(),
+        picture_in_picture,
+    );
+
+    renderer_window.set_decorations(true)?;
+    renderer_window.set_resizable(true)?;
+    renderer_window.set_skip_taskbar(false)?;
+    // On macOS this also drops the raised level of the fullscreen mode
+    renderer_window.set_always_on_top(picture_in_picture)?;
+    renderer_window.set_size(tauri::Size::Physical(rect.size))?;
+    renderer_window.set_position(Position::Physical(rect.position))?;
+    Ok(())
+}
+
+/// Where in the work area `place_windowed` puts a renderer, 16:9 and in
+/// physical pixels
+fn windowed_rect(
+    area: &PhysicalRect<i32, u32>,
+    scale_factor: f64,
+    picture_in_picture: bool,
+) -> PhysicalRect<i32, u32> {
+    let share = if picture_in_picture {
+        PIP_WIDTH
+    } else {
+        WINDOWED_WIDTH
+    };
+    let width = (area.size.width as f64 * share) as u32;
+    let height = (width * 9 / 16).min(area.size.height);
+
+    let (x, y) = if picture_in_picture {
+        let margin = (PIP_MARGIN * scale_factor) as i32;
+        (
+            area.position.x + (area.size.width - width) as i32 - margin,
+            area.position.y + (area.size.height - height) as i32 - margin,
+        )
+    } else {
+        (
+            area.position.x + (area.size.width - width) as i32 / 2,
+            area.position.y + (area.size.height - height) as i32 / 2,
+        )
+    };
+    PhysicalRect {
+        position: PhysicalPosition { x, y },
+        size: PhysicalSize { width, height },
+    }
+}
+
+/// The mode an output's renderer is in, fullscreen if it isn't open
+fn current_mode(app: &tauri::AppHandle, output_id: &str) -> RendererMode {
+    let state = app.state::<RendererState>();
+    let outputs = state.outputs.lock().unwrap();
+    outputs
+        .get(output_id)
+        .map(|output| output.mode)
+        .unwrap_or_default()
+}
+
+/// Remember where an output went, so it lands there again next time. Only
+/// fullscreen placements are saved, previews always go on the operator's
+/// display.
+fn assign(app: &tauri::AppHandle, output_id: &str, mode: RendererMode, monitor: &MonitorKey) {
+    if mode == RendererMode::Fullscreen {
+        if let Err(e) = monitors::save_assignment(app, output_id, monitor) {
+            log::error!("Failed to save monitor for output {}: {}", output_id, e);
+        }
+    }
+    let state = app.state::<RendererState>();
+    if let Some(output) = state.outputs.lock().unwrap().get_mut(output_id) {
+        output.monitor = monitor.clone();
+    }
+}
+
+/// Open or navigate the renderer window of an output to a specified URL.
+/// Without an output id this drives the main projector. The monitor is picked
+/// by `monitor`, or `mindex`, or else where the output was last shown; windowed
+/// and picture-in-picture renderers default to the operator's display. Without
+/// a mode an open renderer keeps its mode and a new one goes fullscreen.
+#[tauri::command]
+pub async fn open_renderer(
+    app: tauri::AppHandle,
+    url: String,
+    mindex: Option<usize>,
+    monitor: Option<MonitorKey>,
+    output_id: Option<String>,
+    mode: Option<RendererMode>,
+) -> Result<(), RendererError> {
+    let output_id = output_id.unwrap_or_else(|| DEFAULT_OUTPUT_ID.to_string());
+    validate_output_id(&output_id)?;
+    let url = validate_url(&app, &url)?;
+    let label = renderer_label(&output_id);
+    let mode = mode.unwrap_or_else(|| current_mode(&app, &output_id));
+    let target = match mode {
+        RendererMode::Windowed | RendererMode::PictureInPicture
+            if mindex.is_none() && monitor.is_none() =>
+        {
+            operator_monitor(&app)?
+        }
+        _ => resolve_monitor(&app, &output_id, mindex, monitor)?,
+    };
+
+    let renderer_window = match app.get_webview_window(&label) {
+        Some(window) => window,
+        None => {
+            let window = tauri::WebviewWindowBuilder::new(
+                &app,
+                &label,
+                tauri::WebviewUrl::External(url.clone()),
+            )
+            .title(format!("TheOpenPresenter Renderer ({})", output_id))
+            .fullscreen(false)
+            .decorations(false)
+            .build()?;
+
+            // Keep the cover on the renderer, and forget the output once its
+            // window is gone, however it closed
+            let app_for_event = app.clone();
+            let output_for_event = output_id.clone();
+            window.on_window_event(move |event| match event {
+                WindowEvent::Moved(_) | WindowEvent::Resized(_) => {
+                    renderer_overlay::follow(&app_for_event, &output_for_event);
+                }
+                WindowEvent::Destroyed => {
+                    renderer_overlay::remove(&app_for_event, &output_for_event);
+                    renderer_watchdog::forget(&app_for_event, &output_for_event);
+                    let state = app_for_event.state::<RendererState>();
+                    state.outputs.lock().unwrap().remove(&output_for_event);
+                    keep_awake::refresh(&app_for_event);
+                }
+                _ => {}
+            });
+            window
+        }
+    };
+
+    if renderer_window.url().ok().as_ref() != Some(&url) {
+        renderer_window.navigate(url.clone())?;
+    }
+
+    let key = MonitorKey::of(&target);
+    let kiosk = mode == RendererMode::Fullscreen && kiosk_on(&app, &output_id, &key);
+    place(&app, &renderer_window, mode, &target, kiosk)?;
     renderer_window.show()?;
 
+    app.state::<RendererState>().outputs.lock().unwrap().insert(
+        output_id.clone(),
+        RendererOutput {
+            url: url.to_string(),
+            monitor: key.clone(),
+            mode,
+            displaced: false,
+            kiosk,
+        },
+    );
+    assign(&app, &output_id, mode, &key);
+    renderer_overlay::follow(&app, &output_id);
+    keep_awake::refresh(&app);
+
+    Ok(())
+}
+
+/// Move an open renderer to another monitor, picked by `monitor` or `mindex`.
+/// It keeps its mode.
+#[tauri::command]
+pub async fn move_renderer(
+    app: tauri::AppHandle,
+    output_id: String,
+    mindex: Option<usize>,
+    monitor: Option<MonitorKey>,
+) -> Result<(), RendererError> {
+    let renderer_window = renderer_window(&app, &output_id)
+        .ok_or_else(|| RendererError::NotOpen(output_id.clone()))?;
+    let target = resolve_monitor(&app, &output_id, mindex, monitor)?;
+    let mode = current_mode(&app, &output_id);
+    let key = MonitorKey::of(&target);
+    let kiosk = mode == RendererMode::Fullscreen && kiosk_on(&app, &output_id, &key);
+
+    place(&app, &renderer_window, mode, &target, kiosk)?;
+    renderer_window.show()?;
+    set_displaced(&app, &output_id, false);
+    set_kiosk(&app, &output_id, kiosk);
+    assign(&app, &output_id, mode, &key);
+    renderer_overlay::follow(&app, &output_id);
+    keep_awake::refresh(&app);
+    Ok(())
+}
+
+/// Choose whether an output's renderer is hidden or moved to the primary
+/// monitor while its own monitor is unplugged
+#[tauri::command]
+pub fn set_renderer_disconnect_action(
+    app: tauri::AppHandle,
+    output_id: String,
+    action: DisconnectAction,
+) -> Result<(), String> {
+    monitors::save_disconnect_action(&app, &output_id, action)
+}
+
+/// Turn the kiosk level of an output on or off: above taskbars and
+/// notifications, out of the taskbar, and the display kept awake while its
+/// fullscreen renderer is live. On by default, but never applied on the
+/// operator's monitor or while the renderer stands in on another monitor.
+#[tauri::command]
+pub fn set_renderer_kiosk(
+    app: tauri::AppHandle,
+    output_id: String,
+    kiosk: bool,
+) -> Result<(), String> {
+    monitors::save_kiosk(&app, &output_id, kiosk)?;
+    let output = app
+        .state::<RendererState>()
+        .outputs
+        .lock()
+        .unwrap()
+        .get(&output_id)
+        .cloned();
+    let placed =
+        output.filter(|output| output.mode == RendererMode::Fullscreen && !output.displaced);
+    if let (Some(output), Some(renderer_window)) = (placed, renderer_window(&app, &output_id)) {
+        let kiosk = kiosk_on(&app, &output_id, &output.monitor);
+        apply_kiosk(&app, &renderer_window, kiosk).map_err(|e| e.to_string())?;
+        set_kiosk(&app, &output_id, kiosk);
+        renderer_overlay::follow(&app, &output_id);
+    }
+    keep_awake::refresh(&app);
+    Ok(())
+}
+
+/// Close the renderer of an output. Closing one that isn't open is a no-op.
+#[tauri::command]
+pub async fn close_renderer(app: tauri::AppHandle, output_id: String) -> Result<(), RendererError> {
+    if let Some(renderer_window) = renderer_window(&app, &output_id) {
+        renderer_window.destroy()?;
+    }
+    app.state::<RendererState>()
+        .outputs
+        .lock()
+        .unwrap()
+        .remove(&output_id);
+    keep_awake::refresh(&app);
     Ok(())
 }
+
+/// List the open renderers, sorted by output id
+#[tauri::command]
+pub fn list_renderers(app: tauri::AppHandle) -> Vec<RendererInfo> {
+    let outputs = app.state::<RendererState>().outputs.lock().unwrap().clone();
+
+    outputs
+        .into_iter()
+        .filter_map(|(output_id, output)| {
+            let renderer_window = renderer_window(&app, &output_id)?;
+            Some(RendererInfo {
+                visible: renderer_window.is_visible().unwrap_or(false),
+                cover: renderer_overlay::cover(&app, &output_id),
+                output_id,
+                url: output.url,
+                monitor: output.monitor,
+                mode: output.mode,
+            })
+        })
+        .collect()
+}
+
+/// Hide every renderer, or show them all again if none is visible.
+/// Renderers whose monitor is unplugged stay where `rehome_renderers` put them.
+pub(crate) fn toggle_all_visible(app: &tauri::AppHandle) {
+    let outputs = app.state::<RendererState>().outputs.lock().unwrap().clone();
+    let windows: Vec<(String, WebviewWindow)> = outputs
+        .into_iter()
+        .filter(|(_, output)| !output.displaced)
+        .filter_map(|(output_id, _)| {
+            let window = renderer_window(app, &output_id)?;
+            Some((output_id, window))
+        })
+        .collect();
+    let any_visible = windows
+        .iter()
+        .any(|(_, window)| window.is_visible().unwrap_or(false));
+
+    for (output_id, window) in windows {
+        let result = if any_visible {
+            window.hide()
+        } else {
+            window.show()
+        };
+        if let Err(e) = result {
+            log::error!("Failed to show or hide renderer {}: {}", output_id, e);
+        }
+        renderer_overlay::follow(app, &output_id);
+    }
+    keep_awake::refresh(app);
+}
+
+/// The URL an output's renderer was last sent to
+pub(crate) fn last_url(app: &tauri::AppHandle, output_id: &str) -> Option<Url> {
+    let state = app.state::<RendererState>();
+    let outputs = state.outputs.lock().unwrap();
+    outputs
+        .get(output_id)
+        .and_then(|output| Url::parse(&output.url).ok())
+}
+
+/// Whether a fullscreen renderer at kiosk level is on screen
+pub(crate) fn kiosk_live(app: &tauri::AppHandle) -> bool {
+    let outputs = app.state::<RendererState>().outputs.lock().unwrap().clone();
+    outputs.into_iter().any(|(output_id, output)| {
+        output.mode == RendererMode::Fullscreen
+            && !output.displaced
+            && output.kiosk
+            && renderer_window(app, &output_id)
+                .is_some_and(|window| window.is_visible().unwrap_or(false))
+    })
+}
+
+/// Output ids of the open renderers
+pub(crate) fn open_outputs(app: &tauri::AppHandle) -> Vec<String> {
+    let state = app.state::<RendererState>();
+    let outputs = state.outputs.lock().unwrap();
+    outputs.keys().cloned().collect()
+}
+
+/// Output ids of the renderers that are on their monitor, with that monitor
+pub(crate) fn placed_renderers(app: &tauri::AppHandle) -> Vec<(String, MonitorKey)> {
+    let outputs = app.state::<RendererState>().outputs.lock().unwrap().clone();
+    outputs
+        .into_iter()
+        .filter(|(_, output)| !output.displaced)
+        .map(|(output_id, output)| (output_id, output.monitor))
+        .collect()
+}
+
+fn set_displaced(app: &tauri::AppHandle, output_id: &str, displaced: bool) {
+    let state = app.state::<RendererState>();
+    if let Some(output) = state.outputs.lock().unwrap().get_mut(output_id) {
+        output.displaced = displaced;
+    }
+}
+
+fn set_kiosk(app: &tauri::AppHandle, output_id: &str, kiosk: bool) {
+    let state = app.state::<RendererState>();
+    if let Some(output) = state.outputs.lock().unwrap().get_mut(output_id) {
+        output.kiosk = kiosk;
+    }
+}
+
+/// Handle monitors being plugged in or out: renderers whose monitor is gone
+/// are paused or re-homed as their assignment says, and go back onto their
+/// monitor once it is connected again
+pub(crate) fn rehome_renderers(app: &tauri::AppHandle, appeared: &[MonitorKey]) {
+    let Ok(connected) = app.available_monitors() else {
+        return;
+    };
+    let assignments = monitors::assignments(app);
+    let outputs = app.state::<RendererState>().outputs.lock().unwrap().clone();
+
+    // Previews follow the operator's window manager, not the projectors
+    let fullscreen = outputs
+        .into_iter()
+        .filter(|(_, output)| output.mode == RendererMode::Fullscreen);
+
+    for (output_id, output) in fullscreen {
+        let Some(renderer_window) = renderer_window(app, &output_id) else {
+            continue;
+        };
+
+        let result = match monitors::find(&connected, &output.monitor) {
+            Some(target) => {
+                // The monitor may have moved when it came back, so place again
+                if !output.displaced && !appeared.contains(&MonitorKey::of(target)) {
+                    continue;
+                }
+                log::info!(
+                    "Monitor for output {} is back, moving its renderer",
+                    output_id
+                );
+                let kiosk = kiosk_on(app, &output_id, &MonitorKey::of(target));
+                set_displaced(app, &output_id, false);
+                set_kiosk(app, &output_id, kiosk);
+                place_on_monitor(app, &renderer_window, target, kiosk)
+                    .and_then(|_| renderer_window.show())
+            }
+            None if output.displaced => continue,
+            None => {
+                let action = assignments
+                    .get(&output_id)
+                    .map(|saved| saved.on_disconnect)
+                    .unwrap_or_default();
+                log::warn!(
+                    "Monitor for output {} is gone, applying {:?} to its renderer",
+                    output_id,
+                    action
+                );
+                set_displaced(app, &output_id, true);
+                set_kiosk(app, &output_id, false);
+                let fallback = app
+                    .primary_monitor()
+                    .ok()
+                    .flatten()
+                    .or_else(|| connected.first().cloned());
+                match (action, fallback) {
+                    // A stand-in usually lands on the operator's monitor, so
+                    // it stays an ordinary window there
+                    (DisconnectAction::Rehome, Some(fallback)) => {
+                        place_on_monitor(app, &renderer_window, &fallback, false)
+                    }
+                    _ => renderer_window.hide(),
+                }
+            }
+        };
+        if let Err(e) = result {
+            log::error!("Failed to move renderer {}: {}", output_id, e);
+        }
+        renderer_overlay::follow(app, &output_id);
+    }
+    keep_awake::refresh(app);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_label_safe_output_ids() {
+        let longest = "x".repeat(MAX_OUTPUT_ID_LEN);
+        for output_id in ["main", "stage-left", "output_2", "A", &longest] {
+            assert!(validate_output_id(output_id).is_ok(), "{:?}", output_id);
+        }
+    }
+
+    #[test]
+    fn rejects_output_ids_that_break_window_labels() {
+        let too_long = "x".repeat(MAX_OUTPUT_ID_LEN + 1);
+        for output_id in ["", "stage left", "a/b", "ü", "main:1", &too_long] {
+            assert!(
+                matches!(
+                    validate_output_id(output_id),
+                    Err(RendererError::InvalidOutputId(_))
+                ),
+                "{:?}",
+                output_id
+            );
+        }
+    }
+
+    fn allowlist() -> Vec<String> {
+        vec!["https://theopenpresenter.com".to_string()]
+    }
+
+    #[test]
+    fn allows_the_local_server_and_listed_origins() {
+        let local = format!("{}/render/org/project?renderer=1", SERVER_HOST);
+        assert!(check_url(&local, &[]).is_ok());
+        assert!(check_url("https://theopenpresenter.com/render/a/b", &allowlist()).is_ok());
+    }
+
+    #[test]
+    fn matches_whole_origins_not_prefixes() {
+        for url in [
+            "http://theopenpresenter.com/render/a/b",
+            "https://theopenpresenter.com:8443/render/a/b",
+            "https://theopenpresenter.com.evil.example/render/a/b",
+            "https://evil.example/?next=https://theopenpresenter.com",
+        ] {
+            assert!(
+                matches!(
+                    check_url(url, &allowlist()),
+                    Err(RendererError::OriginNotAllowed(_))
+                ),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_non_web_urls() {
+        for url in ["not a url", "file:///etc/passwd", "javascript:alert(1)"] {
+            assert!(
+                matches!(
+                    check_url(url, &allowlist()),
+                    Err(RendererError::InvalidUrl(_))
+                ),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn skips_invalid_allowlist_entries() {
+        let allowed = vec![
+            "not an origin".to_string(),
+            "https://theopenpresenter.com".to_string(),
+        ];
+        assert!(check_url("https://theopenpresenter.com/", &allowed).is_ok());
+    }
+
+    fn work_area(x: i32, y: i32, width: u32, height: u32) -> PhysicalRect<i32, u32> {
+        PhysicalRect {
+            position: PhysicalPosition { x, y },
+            size: PhysicalSize { width, height },
+        }
+    }
+
+    #[test]
+    fn windowed_renderers_are_centred_in_the_work_area() {
+        // A second monitor to the right, with a 40px panel at the top
+        let rect = windowed_rect(&work_area(1920, 40, 1920, 1040), 1.0, false);
+        assert_eq!(rect.size, PhysicalSize::new(1152, 648));
+        assert_eq!(rect.position, PhysicalPosition::new(1920 + 384, 40 + 196));
+    }
+
+    #[test]
+    fn picture_in_picture_sits_in_the_bottom_right_corner() {
+        let rect = windowed_rect(&work_area(0, 0, 1920, 1080), 2.0, true);
+        assert_eq!(rect.size, PhysicalSize::new(480, 270));
+        // The margin is in logical pixels, so it doubles on a 2x display
+        assert_eq!(
+            rect.position,
+            PhysicalPosition::new(1920 - 480 - 48, 1080 - 270 - 48)
+        );
+    }
+
+    #[test]
+    fn windowed_renderers_fit_short_work_areas() {
+        let rect = windowed_rect(&work_area(0, 0, 3840, 600), 1.0, false);
+        assert_eq!(rect.size.height, 600);
+        assert_eq!(rect.position.y, 0);
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use tauri::{window::Color, AppHandle, Manager, WebviewUrl, WebviewWindow};
+
+use crate::renderer_commands::{self, RendererError};
+
+/// Overlay windows are this prefix plus the output id and a number
+const OVERLAY_LABEL_PREFIX: &str = "overlay-";
+
+/// What covers a renderer. Blackout and logo are their own native window
+/// stacked on top of the renderer, so they work however badly the page is
+/// behaving, and the page keeps running underneath.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RendererCover {
+    Blackout,
+    Logo,
+    /// Hide the page inside the renderer window without unloading it. The
+    /// audience sees the black window until it is unfrozen, and the page
+    /// picks up where it is by then. Tauri can't hold a webview's last
+    /// frame, so this is as close to a freeze as the window level gets.
+    Freeze,
+}
+
+impl RendererCover {
+    /// Bundled page the overlay window shows, `None` if the cover needs no
+    /// overlay
+    fn page(self) -> Option<&'static str> {
+        match self {
+            Self::Blackout => Some("index.html"),
+            Self::Logo => Some("logo.html"),
+            Self::Freeze => None,
+        }
+    }
+}
+
+/// The cover on each output that has one
+#[derive(Default)]
+pub struct OverlayState {
+    covers: Mutex<HashMap<String, Covered>>,
+    /// Numbers the overlay windows, so a new one can be up before the old
+    /// one goes
+    next_overlay: AtomicU64,
+}
+
+struct Covered {
+    cover: RendererCover,
+    /// Label of the overlay window, if the cover has one
+    overlay: Option<String>,
+}
+
+/// The overlay window of an output, if its cover has one
+fn overlay_window(app: &AppHandle, output_id: &str) -> Option<WebviewWindow> {
+    let label = {
+        let state = app.state::<OverlayState>();
+        let covers = state.covers.lock().unwrap();
+        covers.get(output_id)?.overlay.clone()?
+    };
+    app.get_webview_window(&label)
+}
+
+/// The cover shown on an output, if any
+pub(crate) fn cover(app: &AppHandle, output_id: &str) -> Option<RendererCover> {
+    let state = app.state::<OverlayState>();
+    let covers = state.covers.lock().unwrap();
+    covers.get(output_id).map(|covered| covered.cover)
+}
+
+/// Cover an output's renderer, or uncover it with `None`
+pub(crate) fn set_cover(
+    app: &AppHandle,
+    output_id: &str,
+    cover: Option<RendererCover>,
+) -> Result<(), RendererError> {
+    let renderer_window = renderer_commands::renderer_window(app, output_id)
+        .ok_or_else(|| RendererError::NotOpen(output_id.to_string()))?;
+
+    // A different cover needs a fresh page. Put it up before taking the old
+    // one down, so the page never shows through in between.
+    let overlay = match cover.and_then(RendererCover::page) {
+        Some(page) => Some(open_overlay(app, output_id, page, &renderer_window)?),
+        None => None,
+    };
+    set_frozen(&renderer_window, cover == Some(RendererCover::Freeze))?;
+
+    let state = app.state::<OverlayState>();
+    let previous = {
+        let mut covers = state.covers.lock().unwrap();
+        match cover {
+            Some(cover) => covers.insert(
+                output_id.to_string(),
+                Covered {
+                    cover,
+                    overlay: overlay.map(|overlay| overlay.label().to_string()),
+                },
+            ),
+            None => covers.remove(output_id),
+        }
+    };
+    if let Some(previous) = previous.and_then(|previous| previous.overlay) {
+        if let Some(previous) = app.get_webview_window(&previous) {
+            previous.destroy()?;
+        }
+    }
+    Ok(())
+}
+
+/// Build an overlay window showing `page` and put it over the renderer
+fn open_overlay(
+    app: &AppHandle,
+    output_id: &str,
+    page: &str,
+    renderer_window: &WebviewWindow,
+) -> Result<WebviewWindow, RendererError> {
+    let number = app
+        .state::<OverlayState>()
+        .next_overlay
+        .fetch_add(1, Ordering::Relaxed);
+    let overlay = tauri::WebviewWindowBuilder::new(
+        app,
+        format!("{}{}-{}", OVERLAY_LABEL_PREFIX, output_id, number),
+        WebviewUrl::App(page.into()),
+    )
+    .title("TheOpenPresenter Overlay")
+    .decorations(false)
+    .resizable(false)
+    .skip_taskbar(true)
+    .always_on_top(true)
+    .focused(false)
+    .visible(false)
+    .build()?;
+    overlay.set_ignore_cursor_events(true)?;
+    cover_window(app, renderer_window, &overlay)?;
+    Ok(overlay)
+}
+
+/// Hide or show the page inside a renderer window. A hidden page keeps
+/// running, it just isn't drawn.
+fn set_frozen(renderer_window: &WebviewWindow, frozen: bool) -> tauri::Result<()> {
+    let webview: &tauri::Webview = renderer_window.as_ref();
+    if frozen {
+        renderer_window.set_background_color(Some(Color(0, 0, 0, 255)))?;
+        webview.hide()
+    } else {
+        webview.show()
+    }
+}
+
+/// Put the overlay exactly over the renderer's content, and only show it
+/// while the renderer is shown
+#[cfg_attr(not(target_os = "macos"), allow(unused_variables))]
+fn cover_window(
+    app: &AppHandle,
+    renderer_window: &WebviewWindow,
+    overlay: &WebviewWindow,
+) -> tauri::Result<()> {
+    if !renderer_window.is_visible()? {
+        return overlay.hide();
+    }
+    overlay.set_position(renderer_window.inner_position()?)?;
+    overlay.set_size(renderer_window.inner_size()?)?;
+    // Kiosk renderers are always on top too, so claim the top spot again
+    overlay.set_always_on_top(true)?;
+    overlay.show()?;
+
+    // The fullscreen renderer sits above the menu bar on macOS, so the
+    // overlay has to go higher still
+    #[cfg(target_os = "macos")]
+    renderer_commands::set_window_level(app, overlay, renderer_commands::ABOVE_MENU_BAR_LEVEL + 1)?;
+    Ok(())
+}
+
+/// Keep an output's overlay on its renderer after it moved, resized, was
+/// hidden or shown
+pub(crate) fn follow(app: &AppHandle, output_id: &str) {
+    let Some(overlay) = overlay_window(app, output_id) else {
+        return;
+    };
+    let Some(renderer_window) = renderer_commands::renderer_window(app, output_id) else {
+        return;
+    };
+    if let Err(e) = cover_window(app, &renderer_window, &overlay) {
+        log::error!("Failed to move overlay of output {}: {}", output_id, e);
+    }
+}
+
+/// Drop an output's overlay, e.g. because its renderer closed
+pub(crate) fn remove(app: &AppHandle, output_id: &str) {
+    if let Some(overlay) = overlay_window(app, output_id) {
+        let _ = overlay.destroy();
+    }
+    app.state::<OverlayState>()
+        .covers
+        .lock()
+        .unwrap()
+        .remove(output_id);
+}
+
+/// Cover every open renderer, or uncover them all if they already show
+/// `cover`. Used by the global shortcuts.
+pub(crate) fn toggle_all(app: &AppHandle, cover: RendererCover) {
+    let output_ids = renderer_commands::open_outputs(app);
+    let all_covered = output_ids
+        .iter()
+        .all(|output_id| self::cover(app, output_id) == Some(cover));
+    let next = if all_covered { None } else { Some(cover) };
+
+    for output_id in output_ids {
+        if let Err(e) = set_cover(app, &output_id, next) {
+            log::error!("Failed to cover output {}: {}", output_id, e);
+        }
+    }
+}
+
+/// Black out, show the logo over or freeze an output's renderer, or remove
+/// the cover with `null`. The page keeps running underneath.
+///
+/// Async because it may build the overlay window, which deadlocks on Windows
+/// from a synchronous command.
+#[tauri::command]
+pub async fn set_renderer_cover(
+    app: AppHandle,
+    output_id: String,
+    cover: Option<RendererCover>,
+) -> Result<(), RendererError> {
+    set_cover(&app, &output_id, cover)
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tauri::{AppHandle, Emitter, Manager, WebviewWindow};
+
+use crate::{renderer_commands, renderer_overlay};
+
+/// How long a renderer page may go without a heartbeat before we treat it
+/// as crashed or hung. The page sends one every 2s (see `TauriHandler.tsx`).
+const HANG_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often we look for silent renderers
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Last heartbeat of every renderer page that has sent one. Pages that never
+/// do, like ones from other origins, are left alone.
+#[derive(Default)]
+pub struct WatchdogState {
+    heartbeats: Mutex<HashMap<String, Heartbeat>>,
+}
+
+struct Heartbeat {
+    at: Instant,
+    /// Reloads since the page last responded
+    reloads: u32,
+}
+
+impl WatchdogState {
+    /// Record a heartbeat. Returns how many reloads it took to get the page
+    /// back, if any.
+    fn beat(&self, output_id: &str, now: Instant) -> Option<u32> {
+        let mut heartbeats = self.heartbeats.lock().unwrap();
+        let previous = heartbeats.insert(
+            output_id.to_string(),
+            Heartbeat {
+                at: now,
+                reloads: 0,
+            },
+        );
+        previous
+            .map(|previous| previous.reloads)
+            .filter(|reloads| *reloads > 0)
+    }
+
+    /// Outputs silent for longer than `HANG_TIMEOUT`, with how long and how
+    /// often they were reloaded already
+    fn silent(&self, now: Instant) -> Vec<(String, Duration, u32)> {
+        let heartbeats = self.heartbeats.lock().unwrap();
+        heartbeats
+            .iter()
+            .map(|(output_id, heartbeat)| {
+                (
+                    output_id.clone(),
+                    now.saturating_duration_since(heartbeat.at),
+                    heartbeat.reloads,
+                )
+            })
+            .filter(|(_, silent_for, _)| *silent_for > HANG_TIMEOUT)
+            .collect()
+    }
+
+    /// Restart an output's timeout, keeping count of the reloads so far
+    fn reset(&self, output_id: &str, now: Instant, reloads: u32) {
+        self.heartbeats
+            .lock()
+            .unwrap()
+            .insert(output_id.to_string(), Heartbeat { at: now, reloads });
+    }
+
+    fn forget(&self, output_id: &str) {
+        self.heartbeats.lock().unwrap().remove(output_id);
+    }
+}
+
+/// A renderer that stopped responding, as reported to the frontend
+#[derive(Clone, serde::Serialize)]
+pub struct RendererIncident {
+    pub output_id: String,
+    pub url: String,
+    pub silent_for_ms: u64,
+    pub reloads: u32,
+}
+
+/// Called by renderer pages to show they are alive
+#[tauri::command]
+pub fn renderer_heartbeat(app: AppHandle, window: WebviewWindow) {
+    let Some(output_id) = renderer_commands::output_id_of(window.label()) else {
+        return;
+    };
+    let state = app.state::<WatchdogState>();
+    if let Some(reloads) = state.beat(output_id, Instant::now()) {
+        log::info!(
+            "Renderer {} is responding again after {} reload(s)",
+            output_id,
+            reloads
+        );
+    }
+}
+
+/// Stop watching an output, e.g. because its renderer closed
+pub(crate) fn forget(app: &AppHandle, output_id: &str) {
+    app.state::<WatchdogState>().forget(output_id);
+}
+
+/// Long-running background task: reload renderers whose page stopped sending
+/// heartbeats to the URL they last showed, and log each incident.
+pub(crate) async fn watch_renderers(app: AppHandle) {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+
+        let silent = app.state::<WatchdogState>().silent(Instant::now());
+        for (output_id, silent_for, reloads) in silent {
+            recover(&app, &output_id, silent_for, reloads);
+        }
+    }
+}
+
+fn recover(app: &AppHandle, output_id: &str, silent_for: Duration, reloads: u32) {
+    let (Some(renderer_window), Some(url)) = (
+        renderer_commands::renderer_window(app, output_id),
+        renderer_commands::last_url(app, output_id),
+    ) else {
+        forget(app, output_id);
+        return;
+    };
+
+    let state = app.state::<WatchdogState>();
+    // Browsers throttle timers of hidden or covered pages, so silence means
+    // nothing there. Reloading under a cover would also throw away the page
+    // the operator is about to uncover.
+    let shown = renderer_window.is_visible().unwrap_or(false)
+        && renderer_overlay::cover(app, output_id).is_none();
+    if !shown {
+        state.reset(output_id, Instant::now(), reloads);
+        return;
+    }
+
+    log::error!(
+        "Renderer {} sent no heartbeat for {:?}, reloading {} ({} earlier reload(s))",
+        output_id,
+        silent_for,
+        url,
+        reloads
+    );
+    let _ = app.emit(
+        "renderer-incident",
+        RendererIncident {
+            output_id: output_id.to_string(),
+            url: url.to_string(),
+            silent_for_ms: silent_for.as_millis() as u64,
+            reloads,
+        },
+    );
+
+    // Give the page a full timeout to come back before trying again
+    state.reset(output_id, Instant::now(), reloads + 1);
+    if let Err(e) = renderer_window.navigate(url) {
+        log::error!("Failed to reload renderer {}: {}", output_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_reports_outputs_past_the_timeout() {
+        let state = WatchdogState::default();
+        let start = Instant::now();
+        state.beat("a", start);
+        state.beat("b", start + Duration::from_secs(10));
+
+        let now = start + HANG_TIMEOUT + Duration::from_secs(1);
+        let silent = state.silent(now);
+        assert_eq!(silent.len(), 1);
+        assert_eq!(silent[0].0, "a");
+        assert_eq!(silent[0].1, HANG_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(silent[0].2, 0);
+    }
+
+    #[test]
+    fn reset_restarts_the_timeout_and_keeps_the_reload_count() {
+        let state = WatchdogState::default();
+        let start = Instant::now();
+        state.beat("a", start);
+
+        let reloaded_at = start + HANG_TIMEOUT + Duration::from_secs(1);
+        state.reset("a", reloaded_at, 1);
+        assert!(state.silent(reloaded_at + HANG_TIMEOUT).is_empty());
+
+        let silent = state.silent(reloaded_at + HANG_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(silent[0].2, 1);
+    }
+
+    #[test]
+    fn heartbeat_after_reloads_reports_and_clears_them() {
+        let state = WatchdogState::default();
+        let start = Instant::now();
+        assert_eq!(state.beat("a", start), None);
+        state.reset("a", start, 2);
+
+        assert_eq!(state.beat("a", start), Some(2));
+        assert_eq!(state.beat("a", start), None);
+    }
+
+    #[test]
+    fn forgotten_outputs_are_not_reported() {
+        let state = WatchdogState::default();
+        let start = Instant::now();
+        state.beat("a", start);
+        state.forget("a");
+
+        assert!(state.silent(start + HANG_TIMEOUT * 2).is_empty());
+    }
+}
@@ -0,0 +1,25 @@
+use serde::{de::DeserializeOwned, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+/// Settings file in the app data dir, shared by every persisted preference
+pub const STORE_FILE: &str = "config.json";
+
+/// Read a settings section, falling back to its default when it is missing
+/// or no longer parses (e.g. written by an older version).
+pub(crate) fn load<T: DeserializeOwned + Default>(app: &AppHandle, key: &str) -> T {
+    let Ok(store) = app.store(STORE_FILE) else {
+        return T::default();
+    };
+    store
+        .get(key)
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+/// Write a settings section and flush it to disk
+pub(crate) fn save<T: Serialize>(app: &AppHandle, key: &str, value: &T) -> Result<(), String> {
+    let store = app.store(STORE_FILE).map_err(|e| e.to_string())?;
+    store.set(key, serde_json::to_value(value).map_err(|e| e.to_string())?);
+    store.save().map_err(|e| e.to_string())
+}
@@ -0,0 +1,297 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use tauri::{plugin::TauriPlugin, AppHandle, Emitter, Manager, Wry};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::{
+    renderer_commands,
+    renderer_overlay::{self, RendererCover},
+    settings,
+};
+
+/// Settings key holding the shortcut chosen for each action
+const SETTINGS_KEY: &str = "shortcuts";
+
+/// Something a global shortcut does. Every press is also emitted to the UI
+/// as `global-shortcut`; the remote (`useGlobalShortcuts`) turns the slide
+/// actions and clear into key presses and overlays on the server.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShortcutAction {
+    NextSlide,
+    PreviousSlide,
+    /// Black out every renderer, or bring them all back
+    ToggleBlackout,
+    /// Show the logo on every renderer, or bring them all back
+    ToggleLogo,
+    /// Freeze every renderer, or bring them all back
+    ToggleFreeze,
+    /// Hide every renderer window, or show them all again
+    ToggleRenderers,
+    Clear,
+}
+
+impl ShortcutAction {
+    const ALL: [Self; 7] = [
+        Self::NextSlide,
+        Self::PreviousSlide,
+        Self::ToggleBlackout,
+        Self::ToggleLogo,
+        Self::ToggleFreeze,
+        Self::ToggleRenderers,
+        Self::Clear,
+    ];
+
+    /// Only blackout and logo are bound out of the box. Global shortcuts take the
+    /// keys away from every other app, so the rest is left to the operator.
+    fn default_shortcut(self) -> Option<&'static str> {
+        match self {
+            Self::ToggleBlackout => Some("CommandOrControl+Shift+B"),
+            Self::ToggleLogo => Some("CommandOrControl+Shift+L"),
+            _ => None,
+        }
+    }
+
+    fn run(self, app: &AppHandle) {
+        match self {
+            Self::ToggleBlackout => renderer_overlay::toggle_all(app, RendererCover::Blackout),
+            Self::ToggleLogo => renderer_overlay::toggle_all(app, RendererCover::Logo),
+            Self::ToggleFreeze => renderer_overlay::toggle_all(app, RendererCover::Freeze),
+            Self::ToggleRenderers => renderer_commands::toggle_all_visible(app),
+            // Left to the remote, which knows the project and renderer
+            Self::NextSlide | Self::PreviousSlide | Self::Clear => {}
+        }
+        let _ = app.emit("global-shortcut", self);
+    }
+}
+
+/// The shortcuts currently registered with the OS
+#[derive(Default)]
+pub struct ShortcutBindings {
+    registered: Mutex<BTreeMap<ShortcutAction, Shortcut>>,
+}
+
+/// An action and its shortcut as reported to the frontend
+#[derive(serde::Serialize)]
+pub struct ShortcutBinding {
+    pub action: ShortcutAction,
+    pub shortcut: Option<String>,
+    /// False when the OS refused the shortcut, usually because another app
+    /// already has it
+    pub registered: bool,
+}
+
+/// The shortcut chosen for each action, falling back to the defaults
+fn configured(app: &AppHandle) -> BTreeMap<ShortcutAction, Option<String>> {
+    let saved: BTreeMap<ShortcutAction, Option<String>> = settings::load(app, SETTINGS_KEY);
+    ShortcutAction::ALL
+        .into_iter()
+        .map(|action| {
+            let shortcut = match saved.get(&action) {
+                Some(shortcut) => shortcut.clone(),
+                None => action.default_shortcut().map(String::from),
+            };
+            (action, shortcut)
+        })
+        .collect()
+}
+
+fn parse(shortcut: &str) -> Result<Shortcut, String> {
+    shortcut
+        .parse()
+        .map_err(|e| format!("Invalid shortcut {:?}: {}", shortcut, e))
+}
+
+/// Another action already bound to `shortcut`. Shortcuts are compared
+/// parsed, so `Ctrl+Shift+B` and `shift+control+b` clash.
+fn conflict(
+    configured: &BTreeMap<ShortcutAction, Option<String>>,
+    action: ShortcutAction,
+    shortcut: Shortcut,
+) -> Option<ShortcutAction> {
+    configured
+        .iter()
+        .find(|(other, other_shortcut)| {
+            **other != action
+                && other_shortcut
+                    .as_deref()
+                    .and_then(|other_shortcut| parse(other_shortcut).ok())
+                    == Some(shortcut)
+        })
+        .map(|(other, _)| *other)
+}
+
+/// The global shortcut plugin, dispatching presses to their action
+pub(crate) fn plugin() -> TauriPlugin<Wry> {
+    tauri_plugin_global_shortcut::Builder::new()
+        .with_handler(|app, shortcut, event| {
+            if event.state != ShortcutState::Pressed {
+                return;
+            }
+            let action = {
+                let state = app.state::<ShortcutBindings>();
+                let registered = state.registered.lock().unwrap();
+                registered
+                    .iter()
+                    .find(|(_, bound)| *bound == shortcut)
+                    .map(|(action, _)| *action)
+            };
+            // Covers build overlay windows, which deadlocks on Windows when
+            // done from the event handler itself
+            if let Some(action) = action {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move { action.run(&app) });
+            }
+        })
+        .build()
+}
+
+/// Grab the configured shortcuts from the OS. They work while the Studio is
+/// in the background, which is the point: the operator may be in another app.
+pub(crate) fn register(app: &AppHandle) {
+    let state = app.state::<ShortcutBindings>();
+    let mut registered = state.registered.lock().unwrap();
+
+    for (action, shortcut) in configured(app) {
+        let Some(shortcut) = shortcut else {
+            continue;
+        };
+        let result = parse(&shortcut).and_then(|parsed| {
+            app.global_shortcut()
+                .register(parsed)
+                .map_err(|e| e.to_string())?;
+            Ok(parsed)
+        });
+        match result {
+            Ok(parsed) => {
+                registered.insert(action, parsed);
+            }
+            Err(e) => log::error!("Failed to register {} for {:?}: {}", shortcut, action, e),
+        }
+    }
+}
+
+/// List every action with its shortcut
+#[tauri::command]
+pub fn get_shortcuts(app: AppHandle) -> Vec<ShortcutBinding> {
+    let state = app.state::<ShortcutBindings>();
+    let registered = state.registered.lock().unwrap();
+
+    configured(&app)
+        .into_iter()
+        .map(|(action, shortcut)| ShortcutBinding {
+            registered: registered.contains_key(&action),
+            action,
+            shortcut,
+        })
+        .collect()
+}
+
+/// Bind an action to a shortcut like `CommandOrControl+Shift+B`, or unbind it
+/// with `null`. Fails without changing anything if another action or another
+/// app already uses the shortcut.
+#[tauri::command]
+pub fn set_shortcut(
+    app: AppHandle,
+    action: ShortcutAction,
+    shortcut: Option<String>,
+) -> Result<(), String> {
+    let parsed = shortcut.as_deref().map(parse).transpose()?;
+
+    let mut configured = configured(&app);
+    if let Some(other) = parsed.and_then(|parsed| conflict(&configured, action, parsed)) {
+        return Err(format!(
+            "{} is already the shortcut for {:?}",
+            shortcut.as_deref().unwrap_or_default(),
+            other
+        ));
+    }
+
+    let state = app.state::<ShortcutBindings>();
+    let mut registered = state.registered.lock().unwrap();
+    let global_shortcut = app.global_shortcut();
+    let previous = registered.remove(&action);
+    if let Some(previous) = previous {
+        let _ = global_shortcut.unregister(previous);
+    }
+    if let Some(parsed) = parsed {
+        if let Err(e) = global_shortcut.register(parsed) {
+            // Put the old shortcut back so a failed change is no change
+            if let Some(previous) = previous {
+                if global_shortcut.register(previous).is_ok() {
+                    registered.insert(action, previous);
+                }
+            }
+            return Err(format!(
+                "Could not use {}, another app may have it: {}",
+                shortcut.as_deref().unwrap_or_default(),
+                e
+            ));
+        }
+        registered.insert(action, parsed);
+    }
+    drop(registered);
+
+    configured.insert(action, shortcut);
+    settings::save(&app, SETTINGS_KEY, &configured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(pairs: &[(ShortcutAction, &str)]) -> BTreeMap<ShortcutAction, Option<String>> {
+        ShortcutAction::ALL
+            .into_iter()
+            .map(|action| {
+                let shortcut = pairs
+                    .iter()
+                    .find(|(bound, _)| *bound == action)
+                    .map(|(_, shortcut)| shortcut.to_string());
+                (action, shortcut)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_another_action_with_the_same_shortcut() {
+        let configured = bindings(&[(ShortcutAction::ToggleBlackout, "CommandOrControl+Shift+B")]);
+        let shortcut = parse("CommandOrControl+Shift+B").unwrap();
+        assert_eq!(
+            conflict(&configured, ShortcutAction::NextSlide, shortcut),
+            Some(ShortcutAction::ToggleBlackout)
+        );
+    }
+
+    #[test]
+    fn compares_shortcuts_parsed() {
+        let configured = bindings(&[(ShortcutAction::ToggleLogo, "Control+Shift+L")]);
+        let shortcut = parse("shift+ctrl+l").unwrap();
+        assert_eq!(
+            conflict(&configured, ShortcutAction::Clear, shortcut),
+            Some(ShortcutAction::ToggleLogo)
+        );
+    }
+
+    #[test]
+    fn rebinding_an_action_to_its_own_shortcut_is_no_conflict() {
+        let configured = bindings(&[(ShortcutAction::ToggleBlackout, "CommandOrControl+Shift+B")]);
+        let shortcut = parse("CommandOrControl+Shift+B").unwrap();
+        assert_eq!(
+            conflict(&configured, ShortcutAction::ToggleBlackout, shortcut),
+            None
+        );
+    }
+
+    #[test]
+    fn unbound_and_unparsable_shortcuts_never_conflict() {
+        let configured = bindings(&[(ShortcutAction::NextSlide, "not a shortcut")]);
+        let shortcut = parse("PageDown").unwrap();
+        assert_eq!(
+            conflict(&configured, ShortcutAction::PreviousSlide, shortcut),
+            None
+        );
+    }
+}